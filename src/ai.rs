@@ -1,160 +1,734 @@
-use crate::board::Board;
-use crate::pgn::move2pgn;
-use crate::piece::{Action, Color, Piece};
-use crate::pos::Pos;
+use crate::board::{Board, Square};
+use crate::deploy::{check_square, simple_setup, DeployError, Recruit, Zone};
+use crate::eval::{Evaluator, MaterialEvaluator};
+use crate::game::Game;
+use crate::moves::Move;
+use crate::pgn::move2san;
+use crate::piece::Color;
+use crate::pos::{Pos, LOS};
+use crate::transposition::{Bound, Entry, TranspositionTable};
 use itertools::Itertools;
+use rand::seq::SliceRandom;
 use rand::Rng;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn piece_value(piece: Piece) -> f32 {
-    match piece {
-        Piece::Pawn {
-            orientation: _,
-            status: _,
-        } => 1.,
-        Piece::Knight => 3.,
-        Piece::Bishop => 3.5,
-        Piece::Rook => 5.,
-        Piece::Queen => 9.,
-        Piece::King => 1000.,
+// the depth searched when no other limit is given
+pub const MAX_DEPTH: u32 = 64;
+// the number of positions remembered between searches
+pub const TABLE_SIZE: usize = 1 << 16;
+// how much a capture can gain besides the captured material, through the position
+const DELTA_MARGIN: f32 = 2.;
+// how many checks can extend a single line, checks answered by checks would extend it forever
+const MAX_EXTENSIONS: u32 = 8;
+// the score of a side that lost its king, more than any material, minus the plies it took to lose it
+const KING_LOST: f32 = 100_000.;
+// any score past this one is a lost king rather than material
+const KING_LOST_BOUND: f32 = KING_LOST / 2.;
+
+fn score2table(score: f32, ply: u32) -> f32 {
+    // lost kings are counted from the root, the table counts them from the position since it can be reached at any ply
+    if score > KING_LOST_BOUND {
+        score + ply as f32
+    } else if score < -KING_LOST_BOUND {
+        score - ply as f32
+    } else {
+        score
     }
 }
 
-fn axis_value(x: i32, len: usize) -> f32 {
-    // score a single axis of a position, gives more value to center
-    0.5 - f32::abs(x as f32 / (len - 1) as f32 - 0.5)
+fn table2score(score: f32, ply: u32) -> f32 {
+    if score > KING_LOST_BOUND {
+        score - ply as f32
+    } else if score < -KING_LOST_BOUND {
+        score + ply as f32
+    } else {
+        score
+    }
 }
 
-fn pos_value(board: &Board, pos: Pos) -> f32 {
-    // score a position, gives more value to center
-    axis_value(pos.0, board.width) * axis_value(pos.1, board.height)
+pub fn score2mate(score: f32) -> Option<i32> {
+    // the moves until the side to move mates, negative when it gets mated, None for any other score,
+    // the king is only taken the move after the mate
+    if score.abs() <= KING_LOST_BOUND {
+        return None;
+    }
+    let moves = ((KING_LOST - score.abs()) as i32 - 1) / 2;
+    Some(if score > 0. { moves } else { -moves })
 }
 
-fn move_value(board: &Board, pos: Pos, actions: &Vec<Action>) -> f32 {
+fn move_value(evaluator: &dyn Evaluator, board: &Board, mv: Move) -> f32 {
     // compute the material value of a move, *assuming that if the moves win any material the piece is lost*
-    let (color, piece) = board.get(pos).unwrap().unwrap();
+    let (color, piece) = board.get(mv.from()).unwrap().piece().unwrap();
     let mut value = 0.;
-    for action in actions {
-        match *action {
-            Action::Go(go_pos) => {
-                if let Some(Some((o_color, o_piece))) = board.get(go_pos) {
-                    value += piece_value(*o_piece) * if *o_color == color { -1. } else { 1. };
-                }
-            }
-            Action::Take(take_pos) => {
-                if let Some(Some((o_color, o_piece))) = board.get(take_pos) {
-                    value += piece_value(*o_piece) * if *o_color == color { -1. } else { 1. };
-                }
-            }
-            Action::Promotion(n_piece) => {
-                value += piece_value(n_piece);
-            }
+    for take_pos in [Some(mv.to()), mv.take()].iter().flatten() {
+        if let Some(Square::Piece(o_color, o_piece)) = board.get(*take_pos) {
+            value += evaluator.piece_value(*o_piece) * if *o_color == color { -1. } else { 1. };
         }
     }
+    if let Some(n_piece) = mv.promotion() {
+        value += evaluator.piece_value(n_piece);
+    }
     if value > 0. {
-        value -= piece_value(piece);
+        value -= evaluator.piece_value(piece);
     }
     value
 }
 
-fn mat_pos_score(board: &Board, player: Color) -> f32 {
-    board
-        .squares
-        .iter()
-        .enumerate()
-        .map(|(i, square)| {
-            if let Some((color, piece)) = square {
-                (piece_value(*piece) + pos_value(board, board.pos(i)))
-                    * if *color == player { 1. } else { -1. }
-            } else {
-                0.
+fn material_gain(evaluator: &dyn Evaluator, board: &Board, color: Color, mv: Move) -> f32 {
+    // the material a move wins by capturing or promoting, only positive for captures and promotions
+    let (_, piece) = board.get(mv.from()).unwrap().piece().unwrap();
+    let mut gain = 0.;
+    for take_pos in [Some(mv.to()), mv.take()].iter().flatten() {
+        if let Some(Square::Piece(o_color, o_piece)) = board.get(*take_pos) {
+            if *o_color != color {
+                gain += evaluator.piece_value(*o_piece);
             }
-        })
-        .fold(0., |a, b| a + b)
+        }
+    }
+    if let Some(n_piece) = mv.promotion() {
+        gain += evaluator.piece_value(n_piece) - evaluator.piece_value(piece)
+    }
+    gain
 }
 
-fn _negamax(board: &Board, depth: u32, mut alpha: f32, beta: f32, color: Color) -> f32 {
-    if depth == 0 {
-        return mat_pos_score(board, color);
-    } else {
-        // get all possible moves, including potentially illegal ones (they won't be played but still help evaluating position)
-        let mut all_moves = board.moves(color, false);
-        // for the last depth we don't consider moves with value < 0, they are unsafe.
-        if depth == 1 {
-            all_moves = all_moves
-                .into_iter()
-                .filter(|(pos, actions)| move_value(board, *pos, actions) >= 0.)
-                .collect();
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    // how deep to search at most, None means until another limit is hit
+    pub depth: Option<u32>,
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+    // how many threads split the root moves between them, 0 and 1 both mean a single deterministic thread
+    pub threads: usize,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        Self {
+            depth: Some(depth),
+            ..Self::default()
         }
-        // sort the moves with move_value heuristic
-        all_moves.sort_by(|(pos1, actions1), (pos2, actions2)| {
-            move_value(board, *pos2, actions2)
-                .partial_cmp(&move_value(board, *pos1, actions1))
-                .unwrap()
-        });
-        let mut best_score = f32::NEG_INFINITY;
-        for (pos, actions) in all_moves {
-            best_score = f32::max(
-                best_score,
-                -_negamax(
-                    &board.play(color, pos, &actions),
-                    depth - 1,
-                    -beta,
-                    -alpha,
-                    color.next(),
-                ),
-            );
-            alpha = f32::max(alpha, best_score);
-            if alpha >= beta {
-                break;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub score: f32,
+    pub best_move: Move,
+    // the moves both sides are expected to play, starting with best_move,
+    // it can stop short of the depth where the transposition table cut the search
+    pub pv: Vec<Move>,
+    // the score of every root move searched in the last iteration, best first,
+    // the others are upper bounds since they only had to be proven worse than the best one
+    pub root_scores: Vec<(Move, f32)>,
+    // the deepest depth that was searched, even partially
+    pub depth: u32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+// the state of a search shared by all its threads
+struct SharedSearch<'a> {
+    limits: SearchLimits,
+    evaluator: &'a dyn Evaluator,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: AtomicU64,
+    aborted: AtomicBool,
+    // the threads share what they learn about positions
+    table: &'a TranspositionTable,
+}
+
+struct SearchContext<'a> {
+    shared: &'a SharedSearch<'a>,
+    // the checks that extended the line being searched
+    extensions: u32,
+    // how far the position being searched is from the root
+    ply: u32,
+    aborted: bool,
+}
+
+impl<'a> SearchContext<'a> {
+    fn new(shared: &'a SharedSearch<'a>) -> Self {
+        Self {
+            shared,
+            extensions: 0,
+            ply: 0,
+            aborted: false,
+        }
+    }
+
+    fn should_abort(&mut self) -> bool {
+        // checked at every node, once aborted the rest of the search unwinds immediately
+        if !self.aborted {
+            let shared = self.shared;
+            self.aborted = shared.aborted.load(Ordering::Relaxed)
+                || shared.stop.load(Ordering::Relaxed)
+                || shared
+                    .limits
+                    .nodes
+                    .is_some_and(|nodes| shared.nodes.load(Ordering::Relaxed) >= nodes)
+                || shared
+                    .limits
+                    .time
+                    .is_some_and(|time| shared.start.elapsed() >= time);
+            if self.aborted {
+                // let the other threads know
+                shared.aborted.store(true, Ordering::Relaxed);
             }
         }
-        return best_score;
+        self.aborted
+    }
+
+    fn extend(&mut self, board: &Board, color: Color) -> u32 {
+        // a side in check has few replies, they don't count toward the depth so that a check can't push a threat past the horizon,
+        // returns the extension given to the line, to take back once it's searched
+        if self.extensions < MAX_EXTENSIONS
+            && board.is_checked(color)
+            && !board.is_checked(color.next())
+        {
+            self.extensions += 1;
+            1
+        } else {
+            0
+        }
+    }
+
+    fn count_node(&self) {
+        self.shared.nodes.fetch_add(1, Ordering::Relaxed);
     }
 }
 
-pub fn minmax(board: &Board, color: Color, depth: u32) -> Option<(Pos, Vec<Action>)> {
-    let all_moves = board.moves(color, true);
+fn quiesce(board: &Board, mut alpha: f32, beta: f32, color: Color, ctx: &mut SearchContext) -> f32 {
+    // past the nominal depth only captures and promotions are searched, until the position is quiet,
+    // so that the evaluation doesn't stop in the middle of an exchange
+    if ctx.should_abort() {
+        return 0.;
+    }
+    ctx.count_node();
+    if board.lost_king(color) {
+        return -KING_LOST + ctx.ply as f32;
+    }
+    // the side to move can always decline to capture
+    let evaluator = ctx.shared.evaluator;
+    let stand_pat = evaluator.evaluate(board, color);
+    if stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = f32::max(alpha, stand_pat);
+    // every capture and promotion, with the material it wins and the value of the piece that moves
+    let mut noisy_moves: Vec<(f32, f32, Move)> = board
+        .moves(color, false)
+        .iter()
+        .filter(|mv| mv.is_capture() || mv.promotion().is_some())
+        .map(|mv| {
+            let attacker = board.get(mv.from()).unwrap().piece().unwrap().1;
+            (
+                material_gain(evaluator, board, color, *mv),
+                evaluator.piece_value(attacker),
+                *mv,
+            )
+        })
+        .filter(|(gain, _, _)| *gain > 0.)
+        .collect();
+    // most valuable victim first, least valuable attacker first among them
+    noisy_moves.sort_by(|(gain1, attacker1, _), (gain2, attacker2, _)| {
+        gain2
+            .partial_cmp(gain1)
+            .unwrap()
+            .then(attacker1.partial_cmp(attacker2).unwrap())
+    });
+    let mut best_score = stand_pat;
+    for (gain, _, mv) in noisy_moves {
+        // delta pruning: if even winning this material with some margin can't raise alpha, the next moves can't either
+        if stand_pat + gain + DELTA_MARGIN <= alpha {
+            break;
+        }
+        ctx.ply += 1;
+        let score = -quiesce(&board.play(color, mv), -beta, -alpha, color.next(), ctx);
+        ctx.ply -= 1;
+        best_score = f32::max(best_score, score);
+        alpha = f32::max(alpha, score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best_score
+}
+
+fn _negamax(
+    board: &Board,
+    depth: u32,
+    mut alpha: f32,
+    mut beta: f32,
+    color: Color,
+    ctx: &mut SearchContext,
+    pv: &mut Vec<Move>,
+) -> f32 {
+    if board.lost_king(color) {
+        // losing the king ends the line, the sooner the worse
+        return -KING_LOST + ctx.ply as f32;
+    }
+    if depth == 0 {
+        return quiesce(board, alpha, beta, color, ctx);
+    }
+    if ctx.should_abort() {
+        // the score doesn't matter, it will be discarded
+        return 0.;
+    }
+    ctx.count_node();
+    let hash = board.hash(color);
+    let original_alpha = alpha;
+    let mut table_move = None;
+    if let Some(entry) = ctx.shared.table.get(hash) {
+        if entry.depth >= depth {
+            let score = table2score(entry.score, ctx.ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower => alpha = f32::max(alpha, score),
+                Bound::Upper => beta = f32::min(beta, score),
+            }
+            if alpha >= beta {
+                return score;
+            }
+        }
+        table_move = entry.best_move;
+    }
+    // get all possible moves, including potentially illegal ones (they won't be played but still help evaluating position)
+    // and sort them with move_value heuristic, computed once per move
+    let evaluator = ctx.shared.evaluator;
+    let mut all_moves: Vec<(f32, Move)> = board
+        .moves(color, false)
+        .iter()
+        .map(|mv| (move_value(evaluator, board, *mv), *mv))
+        .collect();
+    all_moves.sort_by(|(value1, _), (value2, _)| value2.partial_cmp(value1).unwrap());
+    // the best move of a previous search goes first, it's checked in case of a hash collision
+    if let Some(i) =
+        table_move.and_then(|table_move| all_moves.iter().position(|(_, m)| *m == table_move))
+    {
+        all_moves[..=i].rotate_right(1);
+    }
     let mut best_score = f32::NEG_INFINITY;
     let mut best_move = None;
-    for (pos, actions) in all_moves {
+    for (_, mv) in all_moves.iter() {
+        let mut child_pv = Vec::new();
+        let child = board.play(color, *mv);
+        let extension = ctx.extend(&child, color.next());
+        ctx.ply += 1;
         let score = -_negamax(
-            &board.play(color, pos, &actions),
-            depth - 1,
-            f32::NEG_INFINITY,
-            f32::INFINITY,
+            &child,
+            depth - 1 + extension,
+            -beta,
+            -alpha,
             color.next(),
+            ctx,
+            &mut child_pv,
         );
+        ctx.ply -= 1;
+        ctx.extensions -= extension;
+        // only a move that raises alpha has an exact score and can be part of the principal variation
+        if score > alpha {
+            pv.clear();
+            pv.push(*mv);
+            pv.append(&mut child_pv);
+        }
         if score > best_score {
-            best_move = Some((pos, actions));
             best_score = score;
+            best_move = Some(*mv);
+        }
+        alpha = f32::max(alpha, best_score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    // an aborted search is not worth remembering
+    if !ctx.aborted {
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        ctx.shared.table.insert(Entry {
+            hash,
+            depth,
+            score: score2table(best_score, ctx.ply),
+            bound,
+            best_move,
+        });
+    }
+    best_score
+}
+
+#[derive(Default)]
+struct RootBest {
+    // the best score and the index of its move
+    best: Option<(f32, usize)>,
+    pv: Vec<Move>,
+    scores: Vec<(usize, f32)>,
+}
+
+fn search_root_moves(
+    board: &Board,
+    color: Color,
+    depth: u32,
+    moves: &[Move],
+    next: &AtomicUsize,
+    root_best: &Mutex<RootBest>,
+    ctx: &mut SearchContext,
+) {
+    // search the root moves that no other thread took yet
+    loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        let Some(mv) = moves.get(i) else {
+            return;
+        };
+        // the other moves only need to be proven worse than the best one so far
+        let alpha = root_best
+            .lock()
+            .unwrap()
+            .best
+            .map_or(f32::NEG_INFINITY, |(best_score, _)| best_score);
+        let mut child_pv = Vec::new();
+        let child = board.play(color, *mv);
+        let extension = ctx.extend(&child, color.next());
+        ctx.ply += 1;
+        let score = -_negamax(
+            &child,
+            depth - 1 + extension,
+            f32::NEG_INFINITY,
+            -alpha,
+            color.next(),
+            ctx,
+            &mut child_pv,
+        );
+        ctx.ply -= 1;
+        ctx.extensions -= extension;
+        if ctx.aborted {
+            return;
+        }
+        let mut root_best = root_best.lock().unwrap();
+        root_best.scores.push((i, score));
+        if root_best
+            .best
+            .is_none_or(|(best_score, _)| score > best_score)
+        {
+            root_best.best = Some((score, i));
+            root_best.pv = vec![*mv];
+            root_best.pv.append(&mut child_pv);
         }
     }
-    best_move
 }
 
-pub fn random_move(board: &Board, color: Color) -> Option<(Pos, Vec<Action>)> {
+pub fn search<E: Evaluator>(
+    board: &Board,
+    color: Color,
+    limits: SearchLimits,
+    evaluator: &E,
+    table: &TranspositionTable,
+    stop: &AtomicBool,
+    mut on_iteration: impl FnMut(&SearchResult),
+) -> Option<SearchResult> {
+    // iterative deepening until a limit is hit or stop is set from another thread,
+    // returns the best move found so far, None if there's no legal move,
+    // the table is kept by the caller so that the next searches start from what this one learned,
+    // it must be cleared when the evaluator changes
+    let shared = SharedSearch {
+        limits,
+        evaluator,
+        stop,
+        start: Instant::now(),
+        nodes: AtomicU64::new(0),
+        aborted: AtomicBool::new(false),
+        table,
+    };
+    let mut contexts: Vec<SearchContext> = (0..limits.threads.max(1))
+        .map(|_| SearchContext::new(&shared))
+        .collect();
+    let mut all_moves = board.moves(color, true);
+    let mut result: Option<SearchResult> = None;
+    for depth in 1..=limits.depth.unwrap_or(MAX_DEPTH) {
+        let next = AtomicUsize::new(0);
+        let root_best = Mutex::new(RootBest::default());
+        if contexts.len() == 1 {
+            search_root_moves(
+                board,
+                color,
+                depth,
+                &all_moves,
+                &next,
+                &root_best,
+                &mut contexts[0],
+            );
+        } else {
+            // the previous best move alone gives a good bound for the other moves, which are then split between threads
+            search_root_moves(
+                board,
+                color,
+                depth,
+                &all_moves[..1],
+                &next,
+                &root_best,
+                &mut contexts[0],
+            );
+            next.store(1, Ordering::Relaxed);
+            thread::scope(|scope| {
+                for ctx in contexts.iter_mut() {
+                    let (all_moves, next, root_best) = (&all_moves, &next, &root_best);
+                    scope.spawn(move || {
+                        search_root_moves(board, color, depth, all_moves, next, root_best, ctx)
+                    });
+                }
+            });
+        }
+        let RootBest { best, pv, scores } = root_best.into_inner().unwrap();
+        // the previous best move is searched first, so a partial iteration is still an improvement,
+        // unless nothing completed at all
+        let Some((score, i)) = best else {
+            break;
+        };
+        // the moves come in search order, with the best first among those that tied with it
+        let mut scores = scores;
+        scores.sort_by_key(|(i, _)| *i);
+        scores.sort_by(|(_, score1), (_, score2)| score2.partial_cmp(score1).unwrap());
+        let root_scores = scores
+            .into_iter()
+            .map(|(i, score)| (all_moves[i], score))
+            .collect();
+        // search the best move first in the next iteration
+        let best_move = all_moves[i];
+        all_moves[..=i].rotate_right(1);
+        let iteration = SearchResult {
+            score,
+            best_move,
+            pv,
+            root_scores,
+            depth,
+            nodes: shared.nodes.load(Ordering::Relaxed),
+            elapsed: shared.start.elapsed(),
+        };
+        on_iteration(&iteration);
+        result = Some(iteration);
+        if shared.aborted.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    if result.is_none() {
+        // aborted before a single move was searched, any legal move is better than none
+        result = all_moves.first().map(|best_move| SearchResult {
+            score: 0.,
+            best_move: *best_move,
+            pv: vec![*best_move],
+            root_scores: Vec::new(),
+            depth: 0,
+            nodes: shared.nodes.load(Ordering::Relaxed),
+            elapsed: shared.start.elapsed(),
+        });
+    }
+    result
+}
+
+pub fn minmax<E: Evaluator>(
+    board: &Board,
+    color: Color,
+    depth: u32,
+    evaluator: &E,
+    table: &TranspositionTable,
+) -> Option<SearchResult> {
+    // a search of a fixed depth
+    search(
+        board,
+        color,
+        SearchLimits::depth(depth),
+        evaluator,
+        table,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+}
+
+pub fn random_move(board: &Board, color: Color, rng: &mut impl Rng) -> Option<Move> {
     let all_moves = board.moves(color, true);
-    if all_moves.len() == 0 {
+    if all_moves.is_empty() {
         return None;
     }
-    Some(all_moves[rand::thread_rng().gen_range(0..all_moves.len())].clone())
+    Some(all_moves[rng.gen_range(0..all_moves.len())])
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DeployOptions {
+    // how many changes to the setup are tried
+    pub iterations: usize,
+    // how deep the enemy's replies are searched to score a setup, 0 to only evaluate it
+    pub depth: u32,
+    // any setup scoring this close to the best one can be picked instead, for variety
+    pub tolerance: f32,
+    // added to the score for every move the pieces have, so that they aren't stuck behind each other
+    pub mobility: f32,
+    // added to the score for every piece next to the king
+    pub shelter: f32,
 }
 
-pub fn auto_play(mut board: Board, starting_player: Color, depth: u32) -> String {
+impl Default for DeployOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 200,
+            depth: 1,
+            tolerance: 0.,
+            mobility: 0.02,
+            shelter: 0.1,
+        }
+    }
+}
+
+fn setup_board(room: &Board, color: Color, setup: &[(Pos, Recruit)]) -> Board {
+    let mut board = room.clone();
+    for (pos, recruit) in setup {
+        board.set(*pos, Square::Piece(color, recruit.piece));
+    }
+    board
+}
+
+fn setup_score<E: Evaluator>(
+    board: &Board,
+    color: Color,
+    evaluator: &E,
+    options: &DeployOptions,
+    table: &TranspositionTable,
+) -> f32 {
+    // the enemy moves first if it's already deployed, so that hanging pieces are punished
+    let score = match board.king_pos(color.next()) {
+        Some(_) if options.depth > 0 => {
+            minmax(board, color.next(), options.depth, evaluator, table)
+                .map_or(evaluator.evaluate(board, color), |result| -result.score)
+        }
+        _ => evaluator.evaluate(board, color),
+    };
+    // pieces that are free to move, and a king with guards around him
+    let mobility = board.moves(color, false).len() as f32 * options.mobility;
+    let shelter = match board.king_pos(color) {
+        Some(king) => {
+            LOS.iter()
+                .filter(|dir| {
+                    matches!(board.get(king + **dir), Some(Square::Piece(p_color, _)) if *p_color == color)
+                })
+                .count() as f32
+                * options.shelter
+        }
+        None => 0.,
+    };
+    score + mobility + shelter
+}
+
+pub fn deploy<E: Evaluator>(
+    room: &Board,
+    zone: &Zone,
+    color: Color,
+    army: &[Recruit],
+    evaluator: &E,
+    options: DeployOptions,
+    rng: &mut impl Rng,
+) -> Result<Vec<(Pos, Recruit)>, DeployError> {
+    // a local search starting from a simple setup, that tries moving a piece to a free square
+    // or swapping 2 pieces and keeps the change if the setup scores at least as well
+    let mut setup = simple_setup(room, zone, color, army, rng)?;
+    // the setups differ by a piece or two, their searches have a lot in common
+    let table = TranspositionTable::new(TABLE_SIZE);
+    let mut score = setup_score(
+        &setup_board(room, color, &setup),
+        color,
+        evaluator,
+        &options,
+        &table,
+    );
+    // the setups within tolerance of the best one so far
+    let mut close = Vec::new();
+    if options.tolerance > 0. {
+        close.push((setup.clone(), score));
+    }
+    for _ in 0..options.iterations {
+        if setup.is_empty() {
+            break;
+        }
+        let mut next = setup.clone();
+        let i = rng.gen_range(0..next.len());
+        let j = rng.gen_range(0..next.len());
+        if rng.gen_bool(0.5) {
+            let board = setup_board(room, color, &setup);
+            let free: Vec<Pos> = zone
+                .squares
+                .iter()
+                .copied()
+                .filter(|pos| check_square(&board, zone, next[i].1.piece, *pos).is_ok())
+                .collect();
+            let Some(pos) = free.choose(rng) else {
+                continue;
+            };
+            next[i].0 = *pos;
+        } else {
+            // both pieces must be able to stand on the other's square
+            let others: Vec<(Pos, Recruit)> = setup
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| *k != i && *k != j)
+                .map(|(_, placed)| *placed)
+                .collect();
+            let board = setup_board(room, color, &others);
+            if next[i].1.piece == next[j].1.piece
+                || check_square(&board, zone, next[i].1.piece, next[j].0).is_err()
+                || check_square(&board, zone, next[j].1.piece, next[i].0).is_err()
+            {
+                continue;
+            }
+            let pos = next[i].0;
+            next[i].0 = next[j].0;
+            next[j].0 = pos;
+        }
+        let next_score = setup_score(
+            &setup_board(room, color, &next),
+            color,
+            evaluator,
+            &options,
+            &table,
+        );
+        let improved = next_score >= score;
+        if improved {
+            score = next_score;
+            close.retain(|(_, close_score)| *close_score >= score - options.tolerance);
+        }
+        if options.tolerance > 0. && next_score >= score - options.tolerance {
+            close.push((next.clone(), next_score));
+        }
+        if improved {
+            setup = next;
+        }
+    }
+    Ok(close
+        .choose(rng)
+        .map_or(setup, |(close_setup, _)| close_setup.clone()))
+}
+
+pub fn auto_play(board: Board, starting_player: Color, depth: u32) -> String {
     let mut pgn_moves: Vec<String> = Vec::new();
-    let mut player = starting_player;
+    let mut game = Game::new(board, starting_player);
+    let table = TranspositionTable::new(TABLE_SIZE);
     let mut turn = 0;
     loop {
-        let move_opt = minmax(&board, player, depth);
-        if move_opt.is_none() {
-            println!("\nNo more valid moves");
+        if let Some(outcome) = game.outcome() {
+            println!("\n{}", outcome);
             break;
         }
-        let (pos, actions) = move_opt.unwrap();
-        let pgn_move = move2pgn(pos, &actions);
+        // there's always a move to play if the game is not over
+        let mv = minmax(&game.board, game.player, depth, &MaterialEvaluator, &table)
+            .unwrap()
+            .best_move;
+        let pgn_move = move2san(&game.board, game.player, mv);
         pgn_moves.push(pgn_move);
-        board = board.play(player, pos, &actions);
-        player = player.next();
+        game.make_move(mv);
         turn += 1;
         if turn >= 100 {
             println!("\nGame too long");
@@ -164,33 +738,241 @@ pub fn auto_play(mut board: Board, starting_player: Color, depth: u32) -> String
     pgn_moves.iter().join(" ")
 }
 
+#[cfg(test)]
 mod tests {
     use crate::{
         ai::auto_play,
         ai::minmax,
+        ai::{
+            deploy, random_move, score2mate, score2table, search, table2score, DeployOptions,
+            SearchLimits, KING_LOST, TABLE_SIZE,
+        },
         board::Board,
+        deploy::{standard_army, Deployment, Recruit, Zone},
+        eval::MaterialEvaluator,
+        fen::fen2board,
         game::invert_color,
         game::standard_board,
-        pgn::move2pgn,
-        piece::{Action, Color},
+        moves::Move,
+        pgn::line2san,
+        piece::{Color, Piece},
         pos::Pos,
+        transposition::TranspositionTable,
     };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn depth_3_fork() {
         let mut board = standard_board();
-        board = board.play(Color::White, Pos(6, 7), &vec![Action::Go(Pos(5, 5))]);
-        board = board.play(Color::Black, Pos(4, 1), &vec![Action::Go(Pos(4, 3))]);
-        board = board.play(Color::White, Pos(4, 6), &vec![Action::Go(Pos(4, 5))]);
-        board = board.play(Color::Black, Pos(3, 1), &vec![Action::Go(Pos(3, 3))]);
-        board = board.play(Color::White, Pos(5, 7), &vec![Action::Go(Pos(3, 5))]);
-        board = board.play(Color::Black, Pos(6, 0), &vec![Action::Go(Pos(5, 2))]);
-        board = board.play(Color::White, Pos(7, 6), &vec![Action::Go(Pos(7, 5))]);
+        board = board.play(Color::White, Move::new(Pos(6, 7), Pos(5, 5)));
+        board = board.play(Color::Black, Move::new(Pos(4, 1), Pos(4, 3)));
+        board = board.play(Color::White, Move::new(Pos(4, 6), Pos(4, 5)));
+        board = board.play(Color::Black, Move::new(Pos(3, 1), Pos(3, 3)));
+        board = board.play(Color::White, Move::new(Pos(5, 7), Pos(3, 5)));
+        board = board.play(Color::Black, Move::new(Pos(6, 0), Pos(5, 2)));
+        board = board.play(Color::White, Move::new(Pos(7, 6), Pos(7, 5)));
         println!("{}\n", board);
-        let (pos, actions) = minmax(&board, Color::Black, 3).unwrap();
-        board = board.play(Color::Black, pos, &actions);
+        let mv = minmax(
+            &board,
+            Color::Black,
+            3,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap()
+        .best_move;
+        board = board.play(Color::Black, mv);
         println!("{}", board);
-        assert!(pos == Pos(4, 3) && actions == vec![Action::Go(Pos(4, 4))]);
+        assert!(mv == Move::new(Pos(4, 3), Pos(4, 4)));
+    }
+
+    #[test]
+    fn quiescence() {
+        // the pawn on d5 is defended, taking it loses the queen right after the horizon
+        let (board, color) = fen2board("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mv = minmax(
+            &board,
+            color,
+            1,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap()
+        .best_move;
+        assert!(mv != Move::new(Pos(3, 7), Pos(3, 3)));
+        // but an undefended one is worth taking
+        let (board, color) = fen2board("4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mv = minmax(
+            &board,
+            color,
+            1,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap()
+        .best_move;
+        assert!(mv == Move::new(Pos(3, 7), Pos(3, 3)));
+        // past the horizon too, whatever white plays the queen takes the pawn
+        let (board, color) = fen2board("3qk3/8/8/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+        let result = minmax(
+            &board,
+            color,
+            1,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap();
+        assert!(result.score < -8.5);
+    }
+
+    #[test]
+    fn principal_variation() {
+        let board = standard_board();
+        let result = minmax(
+            &board,
+            Color::White,
+            3,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap();
+        assert!(result.depth == 3);
+        assert!(result.pv[0] == result.best_move && result.pv.len() <= 3);
+        // every legal move gets a score, none better than the best one
+        assert!(result.root_scores.len() == board.moves(Color::White, true).len());
+        assert!(result.root_scores[0] == (result.best_move, result.score));
+        assert!(result
+            .root_scores
+            .iter()
+            .all(|(_, score)| *score <= result.score));
+        // the mate is expected to end the game
+        let (board, color) = fen2board("7k/p4Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let result = minmax(
+            &board,
+            color,
+            3,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap();
+        let line = line2san(&board, color, &result.pv);
+        assert!(line.len() == 1 && line[0].ends_with('#'));
+    }
+
+    #[test]
+    fn parallel_search() {
+        // the second position has a single best move, e4 forks the bishop and the knight
+        for fen in [
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "rnbqkb1r/ppp2ppp/5n2/3pp3/8/3BPN1P/PPPP1PP1/RNBQK2R b KQkq - 0 4",
+        ] {
+            let (board, color) = fen2board(fen).unwrap();
+            let limits = SearchLimits {
+                threads: 4,
+                ..SearchLimits::depth(3)
+            };
+            let result = search(
+                &board,
+                color,
+                limits,
+                &MaterialEvaluator,
+                &TranspositionTable::new(TABLE_SIZE),
+                &AtomicBool::new(false),
+                |_| {},
+            )
+            .unwrap();
+            let legal_moves = board.moves(color, true);
+            assert!(result.depth == 3 && legal_moves.contains(&result.best_move));
+            assert!(result.root_scores.len() == legal_moves.len());
+            assert!(result
+                .root_scores
+                .iter()
+                .all(|(_, score)| *score <= result.score));
+            // the best score is exact, so the threads find the same one as a single thread,
+            // and the same move unless another one ties with it
+            let single = search(
+                &board,
+                color,
+                SearchLimits::depth(3),
+                &MaterialEvaluator,
+                &TranspositionTable::new(TABLE_SIZE),
+                &AtomicBool::new(false),
+                |_| {},
+            )
+            .unwrap();
+            assert!(result.score == single.score);
+            let tied = single
+                .root_scores
+                .iter()
+                .filter(|(_, score)| *score == single.score)
+                .count();
+            assert!(tied > 1 || result.best_move == single.best_move);
+        }
+    }
+
+    #[test]
+    fn table_reuse() {
+        // a second search of the same position starts from what the first one learned
+        let board = standard_board();
+        let table = TranspositionTable::new(TABLE_SIZE);
+        let first = minmax(&board, Color::White, 3, &MaterialEvaluator, &table).unwrap();
+        let second = minmax(&board, Color::White, 3, &MaterialEvaluator, &table).unwrap();
+        assert!(second.nodes < first.nodes);
+        assert!(second.score == first.score && second.best_move == first.best_move);
+    }
+
+    #[test]
+    fn mate_distance_in_table() {
+        // a king lost 5 plies from the root, learned 2 plies deep, is 3 plies away from that position,
+        // so 7 plies away when the position is reached 4 plies deep
+        let stored = score2table(KING_LOST - 5., 2);
+        assert!(table2score(stored, 4) == KING_LOST - 7.);
+        let stored = score2table(-KING_LOST + 5., 2);
+        assert!(table2score(stored, 4) == -KING_LOST + 7.);
+        // other scores are the same at any ply
+        assert!(table2score(score2table(3.5, 2), 4) == 3.5);
+    }
+
+    #[test]
+    fn mate_scores() {
+        // the king is taken 3 plies after mating in 1, 4 plies after being mated in 1
+        assert!(score2mate(KING_LOST - 3.) == Some(1));
+        assert!(score2mate(KING_LOST - 5.) == Some(2));
+        assert!(score2mate(-KING_LOST + 4.) == Some(-1));
+        assert!(score2mate(-2.5).is_none());
+        let (board, color) = fen2board("7k/p4Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let result = minmax(
+            &board,
+            color,
+            3,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+        )
+        .unwrap();
+        assert!(score2mate(result.score) == Some(1));
+    }
+
+    #[test]
+    fn seeded_random_moves() {
+        // the same seed plays the same game
+        let random_game = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut board = standard_board();
+            let mut color = Color::White;
+            let mut moves = Vec::new();
+            for _ in 0..20 {
+                let mv = random_move(&board, color, &mut rng).unwrap();
+                board = board.play(color, mv);
+                moves.push(mv);
+                color = color.next();
+            }
+            moves
+        };
+        assert!(random_game(5) == random_game(5));
+        assert!(random_game(5) != random_game(6));
     }
 
     #[test]
@@ -201,4 +983,202 @@ mod tests {
         let pgn_moves2 = auto_play(board, Color::Black, 3);
         assert!(pgn_moves1 == pgn_moves2);
     }
+
+    #[test]
+    fn iterative_deepening() {
+        let board = standard_board();
+        let stop = AtomicBool::new(false);
+        let mut depths = Vec::new();
+        let result = search(
+            &board,
+            Color::White,
+            SearchLimits::depth(3),
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+            &stop,
+            |iteration| depths.push(iteration.depth),
+        )
+        .unwrap();
+        assert!(depths == [1, 2, 3]);
+        assert!(
+            result.best_move
+                == minmax(
+                    &board,
+                    Color::White,
+                    3,
+                    &MaterialEvaluator,
+                    &TranspositionTable::new(TABLE_SIZE)
+                )
+                .unwrap()
+                .best_move
+        );
+        // a node budget cuts the search short but still gives a legal move
+        let limits = SearchLimits {
+            nodes: Some(500),
+            ..SearchLimits::default()
+        };
+        let result = search(
+            &board,
+            Color::White,
+            limits,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+            &stop,
+            |_| {},
+        )
+        .unwrap();
+        assert!(result.depth < 64 && result.nodes <= 501);
+        assert!(board.moves(Color::White, true).contains(&result.best_move));
+    }
+
+    #[test]
+    fn time_budget_and_cancel() {
+        let board = standard_board();
+        let stop = AtomicBool::new(false);
+        let limits = SearchLimits {
+            time: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        };
+        let start = Instant::now();
+        assert!(search(
+            &board,
+            Color::White,
+            limits,
+            &MaterialEvaluator,
+            &TranspositionTable::new(TABLE_SIZE),
+            &stop,
+            |_| {}
+        )
+        .is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // without limits only the stop flag ends the search
+        let result = thread::scope(|scope| {
+            let searching = scope.spawn(|| {
+                search(
+                    &board,
+                    Color::White,
+                    SearchLimits::default(),
+                    &MaterialEvaluator,
+                    &TranspositionTable::new(TABLE_SIZE),
+                    &stop,
+                    |_| {},
+                )
+            });
+            thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+            searching.join().unwrap()
+        });
+        assert!(board
+            .moves(Color::White, true)
+            .contains(&result.unwrap().best_move));
+    }
+
+    #[test]
+    fn deploy_safely() {
+        // the bishops watch 2 squares of white's row, the queen and rook must not be put there,
+        // the pawn keeps them from checking the king on its file, and the king is off the bishop's file so that it can't be pinned
+        let room = Board::new(8, 8);
+        let pawn = standard_army()[8];
+        let white = (
+            Zone::rows(&room, Color::White, 1),
+            vec![Piece::King, Piece::Rook, Piece::Queen],
+        );
+        let black = (
+            Zone::rows(&room, Color::Black, 3),
+            vec![Piece::King, Piece::Bishop, Piece::Bishop, pawn],
+        );
+        let mut deployment = Deployment::new(room, white, black).unwrap();
+        for (piece, pos) in [
+            (Piece::King, Pos(6, 0)),
+            (Piece::Bishop, Pos(7, 1)),
+            (Piece::Bishop, Pos(0, 2)),
+            (pawn, Pos(6, 1)),
+        ] {
+            deployment.place(Color::Black, piece, pos).unwrap();
+        }
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let setup = deploy(
+                deployment.board(),
+                deployment.zone(Color::White),
+                Color::White,
+                deployment.army(Color::White),
+                &MaterialEvaluator,
+                DeployOptions::default(),
+                &mut rng,
+            )
+            .unwrap();
+            let mut deployment = deployment.clone();
+            deployment.place_all(Color::White, &setup).unwrap();
+            let game = deployment.start(Color::White).unwrap();
+            for (pos, _) in setup {
+                assert!(!game.board.is_attacked(pos, Color::Black));
+            }
+        }
+    }
+
+    #[test]
+    fn deploy_shelter() {
+        // with shelter worth more than anything else, the rooks stand next to the king
+        let room = Board::new(8, 8);
+        let zone = Zone::rows(&room, Color::White, 2);
+        let army: Vec<Recruit> = vec![Piece::King, Piece::Rook, Piece::Rook]
+            .into_iter()
+            .map(Recruit::from)
+            .collect();
+        let options = DeployOptions {
+            depth: 0,
+            mobility: 0.,
+            shelter: 10.,
+            ..DeployOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let setup = deploy(
+            &room,
+            &zone,
+            Color::White,
+            &army,
+            &MaterialEvaluator,
+            options,
+            &mut rng,
+        )
+        .unwrap();
+        let king = setup
+            .iter()
+            .find(|(_, recruit)| recruit.piece == Piece::King)
+            .unwrap()
+            .0;
+        assert!(setup
+            .iter()
+            .all(|(pos, _)| (pos.0 - king.0).abs() <= 1 && (pos.1 - king.1).abs() <= 1));
+    }
+
+    #[test]
+    fn deploy_variety() {
+        let room = Board::new(8, 8);
+        let zone = Zone::rows(&room, Color::White, 2);
+        let options = DeployOptions {
+            iterations: 30,
+            depth: 0,
+            tolerance: 0.5,
+            ..DeployOptions::default()
+        };
+        let army: Vec<Recruit> = standard_army().into_iter().map(Recruit::from).collect();
+        let setup = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            deploy(
+                &room,
+                &zone,
+                Color::White,
+                &army,
+                &MaterialEvaluator,
+                options,
+                &mut rng,
+            )
+            .unwrap()
+        };
+        assert!(setup(0) == setup(0));
+        assert!((1..5).any(|seed| setup(seed) != setup(0)));
+        assert!(setup(0).len() == 16);
+    }
 }
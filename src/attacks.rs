@@ -0,0 +1,100 @@
+// The squares a piece can reach from every square of a board, computed once per board size
+// so that move generation and check detection don't have to test the board's bounds
+use crate::pos::{Pos, LOS};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// in the order knights always generated their moves
+pub const KNIGHT_JUMPS: [Pos; 8] = [
+    Pos(-2, -1),
+    Pos(-1, -2),
+    Pos(-2, 1),
+    Pos(1, -2),
+    Pos(2, -1),
+    Pos(-1, 2),
+    Pos(2, 1),
+    Pos(1, 2),
+];
+
+type Cache = Mutex<HashMap<(usize, usize), Arc<AttackTables>>>;
+
+pub struct AttackTables {
+    width: usize,
+    height: usize,
+    knight: Vec<Vec<usize>>,
+    king: Vec<Vec<usize>>,
+    // the squares in each direction of LOS until the board's bound, closest first
+    rays: Vec<[Vec<usize>; 8]>,
+}
+
+impl AttackTables {
+    fn new(width: usize, height: usize) -> Self {
+        let in_bound =
+            |pos: Pos| 0 <= pos.0 && pos.0 < width as i32 && 0 <= pos.1 && pos.1 < height as i32;
+        let index = |pos: Pos| (pos.0 + pos.1 * width as i32) as usize;
+        let targets = |pos: Pos, offsets: &[Pos]| {
+            offsets
+                .iter()
+                .map(|offset| pos + *offset)
+                .filter(|target| in_bound(*target))
+                .map(index)
+                .collect()
+        };
+        let positions: Vec<Pos> = (0..width * height)
+            .map(|i| Pos((i % width) as i32, (i / width) as i32))
+            .collect();
+        Self {
+            width,
+            height,
+            knight: positions
+                .iter()
+                .map(|pos| targets(*pos, &KNIGHT_JUMPS))
+                .collect(),
+            king: positions.iter().map(|pos| targets(*pos, &LOS)).collect(),
+            rays: positions
+                .iter()
+                .map(|pos| {
+                    LOS.map(|dir| {
+                        (1..)
+                            .map(|dist| *pos + dir * dist)
+                            .take_while(|target| in_bound(*target))
+                            .map(index)
+                            .collect()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(width: usize, height: usize) -> Arc<Self> {
+        // the tables of a board size are shared by every board of that size
+        static CACHE: OnceLock<Cache> = OnceLock::new();
+        let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+        cache
+            .entry((width, height))
+            .or_insert_with(|| Arc::new(Self::new(width, height)))
+            .clone()
+    }
+
+    pub fn knight(&self, i: usize) -> &[usize] {
+        &self.knight[i]
+    }
+
+    pub fn king(&self, i: usize) -> &[usize] {
+        &self.king[i]
+    }
+
+    pub fn ray(&self, i: usize, dir: usize) -> &[usize] {
+        // dir is an index in LOS
+        &self.rays[i][dir]
+    }
+}
+
+impl PartialEq for AttackTables {
+    fn eq(&self, other: &Self) -> bool {
+        // the tables only depend on the board size
+        self.width == other.width && self.height == other.height
+    }
+}
+
+impl Eq for AttackTables {}
@@ -0,0 +1,34 @@
+use dungeon_chess::fen::{fen2board, STANDARD_FEN};
+use dungeon_chess::perft::divide;
+use dungeon_chess::uci::move2uci;
+use std::time::Instant;
+
+const USAGE: &str = "usage: perft <depth> [<fen>]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(Ok(depth)) = args.first().map(|depth| depth.parse::<u32>()) else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    // the FEN can be given as one argument or as its separate fields
+    let fen = if args.len() > 1 {
+        args[1..].join(" ")
+    } else {
+        STANDARD_FEN.to_string()
+    };
+    let (board, color) = match fen2board(&fen) {
+        Ok(position) => position,
+        Err(err) => {
+            eprintln!("invalid FEN: {}", err);
+            return;
+        }
+    };
+    let start = Instant::now();
+    let mut total = 0;
+    for (mv, nodes) in divide(&board, color, depth) {
+        println!("{}: {}", move2uci(&board, mv), nodes);
+        total += nodes;
+    }
+    println!("\nNodes searched: {} ({:.2?})", total, start.elapsed());
+}
@@ -0,0 +1,6 @@
+use dungeon_chess::uci::Uci;
+
+fn main() {
+    let mut uci = Uci::new(std::io::stdout());
+    uci.run(std::io::stdin().lock());
+}
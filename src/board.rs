@@ -1,23 +1,146 @@
-use crate::piece::{Action, Color, Piece};
-use crate::pos::Pos;
+use crate::attacks::AttackTables;
+use crate::game::Outcome;
+use crate::moves::{Move, MoveList};
+use crate::piece::{Color, Piece};
+use crate::pos::{Pos, LOS};
+use crate::unit::UnitId;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 
-type Square = Option<(Color, Piece)>;
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Square {
+    Empty,
+    // a square that is part of the board but cannot be entered, to shape the rooms
+    Wall,
+    Piece(Color, Piece),
+}
+
+impl Square {
+    pub fn piece(self) -> Option<(Color, Piece)> {
+        if let Square::Piece(color, piece) = self {
+            Some((color, piece))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Terrain {
+    Floor,
+    // sliding pieces can't stop on ice, they glide to the next square
+    Ice,
+    // a piece that ends its move here is destroyed
+    Lava,
+    // only some pieces can enter water, see Piece::can_enter
+    Water,
+    // a piece that ends its move here is sent to the linked square, if it's free
+    Teleporter(Pos),
+    // a piece standing here cannot be captured by pawns
+    Fortification,
+}
+
+pub(crate) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// files are lettered from a to z in notation
+pub const MAX_WIDTH: usize = 26;
+pub const MAX_HEIGHT: usize = 64;
+// xored in the hash when black is to move
+const BLACK_TO_MOVE: u64 = 0x5bd1e9955bd1e995;
+
+fn zobrist_key(i: usize, square: Square) -> u64 {
+    // the key of a square's content, derived from the index and content rather than drawn in tables
+    // since boards come in every size, empty squares have no key
+    let code = match square {
+        Square::Empty => return 0,
+        Square::Wall => 1,
+        Square::Piece(color, piece) => {
+            let piece_code = match piece {
+                Piece::Knight => 2,
+                Piece::Bishop => 3,
+                Piece::Rook => 4,
+                Piece::Queen => 5,
+                Piece::King => 6,
+                Piece::Pawn {
+                    orientation,
+                    status,
+                } => {
+                    let dir = (orientation.0 + 1) * 3 + orientation.1 + 1;
+                    8 + dir as u64 * 3 + status as u64
+                }
+            };
+            piece_code + 64 * color as u64
+        }
+    };
+    splitmix64(((i as u64) << 8) | code)
+}
 
 #[derive(Clone)]
 pub struct Board {
     pub width: usize,
     pub height: usize,
-    pub squares: Vec<Square>,
+    // private so that every change goes through set and keeps the hash up to date
+    squares: Vec<Square>,
+    pub terrain: Vec<Terrain>,
+    // the unit each piece stands for, if it's part of a roster, it doesn't count in the hash
+    units: Vec<Option<UnitId>>,
+    hash: u64,
+    attacks: Arc<AttackTables>,
 }
 
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        // the same position whichever units stand on it, en passant is part of the pawns' status
+        self.width == other.width
+            && self.height == other.height
+            && self.squares == other.squares
+            && self.terrain == other.terrain
+    }
+}
+
+impl Eq for Board {}
+
 impl Board {
     pub fn new(width: usize, height: usize) -> Self {
+        // a board that can't be written down is a bug in what built it
+        assert!(
+            width <= MAX_WIDTH && height <= MAX_HEIGHT,
+            "a board is at most {} by {} squares, not {} by {}",
+            MAX_WIDTH,
+            MAX_HEIGHT,
+            width,
+            height
+        );
         Self {
             width,
             height,
-            squares: vec![None; width * height],
+            squares: vec![Square::Empty; width * height],
+            terrain: vec![Terrain::Floor; width * height],
+            units: vec![None; width * height],
+            hash: 0,
+            attacks: AttackTables::get(width, height),
+        }
+    }
+
+    pub fn squares(&self) -> &[Square] {
+        &self.squares
+    }
+
+    pub fn attacks(&self) -> &AttackTables {
+        &self.attacks
+    }
+
+    pub fn hash(&self, color: Color) -> u64 {
+        // the zobrist hash of the position with color to move
+        match color {
+            Color::White => self.hash,
+            Color::Black => self.hash ^ BLACK_TO_MOVE,
         }
     }
 
@@ -34,20 +157,105 @@ impl Board {
 
     pub fn set(&mut self, pos: Pos, square: Square) {
         let i = self.i(pos);
+        self.hash ^= zobrist_key(i, self.squares[i]) ^ zobrist_key(i, square);
         self.squares[i] = square;
+        if square.piece().is_none() {
+            self.units[i] = None;
+        }
+    }
+
+    pub fn unit(&self, pos: Pos) -> Option<UnitId> {
+        if !self.in_bound(pos) {
+            return None;
+        }
+        self.units[self.i(pos)]
+    }
+
+    pub fn set_unit(&mut self, pos: Pos, unit: Option<UnitId>) {
+        let i = self.i(pos);
+        self.units[i] = unit;
+    }
+
+    pub fn terrain(&self, pos: Pos) -> Terrain {
+        if !self.in_bound(pos) {
+            return Terrain::Floor;
+        }
+        self.terrain[self.i(pos)]
+    }
+
+    pub fn set_terrain(&mut self, pos: Pos, terrain: Terrain) {
+        let i = self.i(pos);
+        self.terrain[i] = terrain;
+    }
+
+    pub fn link_teleporters(&mut self, pos1: Pos, pos2: Pos) {
+        self.set_terrain(pos1, Terrain::Teleporter(pos2));
+        self.set_terrain(pos2, Terrain::Teleporter(pos1));
     }
 
     pub fn pos(&self, i: usize) -> Pos {
-        Pos((i % self.width) as i32, (i / self.height) as i32)
+        Pos((i % self.width) as i32, (i / self.width) as i32)
     }
 
     pub fn i(&self, pos: Pos) -> usize {
         (pos.0 + pos.1 * self.width as i32) as usize
     }
 
-    fn king_pos(&self, color: Color) -> Option<Pos> {
+    pub fn from_layout(layout: &str) -> Self {
+        // build an empty room from rows of characters, rows shorter than the longest one are padded with walls:
+        // '.' floor, '#' wall, '*' ice, '!' lava, '~' water, '+' fortification,
+        // and digits for teleporters, linked to the other square with the same digit
+        let rows: Vec<&str> = layout
+            .lines()
+            .map(|row| row.trim())
+            .filter(|row| !row.is_empty())
+            .collect();
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut board = Board::new(width, rows.len());
+        let mut teleporters: HashMap<char, Pos> = HashMap::new();
+        for (y, row) in rows.iter().enumerate() {
+            let mut chars = row.chars();
+            for x in 0..width {
+                let pos = Pos(x as i32, y as i32);
+                match chars.next() {
+                    Some('.') => {}
+                    Some('*') => board.set_terrain(pos, Terrain::Ice),
+                    Some('!') => board.set_terrain(pos, Terrain::Lava),
+                    Some('~') => board.set_terrain(pos, Terrain::Water),
+                    Some('+') => board.set_terrain(pos, Terrain::Fortification),
+                    Some(c) if c.is_ascii_digit() => {
+                        if let Some(other_pos) = teleporters.remove(&c) {
+                            board.link_teleporters(pos, other_pos);
+                        } else {
+                            teleporters.insert(c, pos);
+                        }
+                    }
+                    _ => board.set(pos, Square::Wall),
+                }
+            }
+        }
+        board
+    }
+
+    pub fn is_edge(&self, pos: Pos, dir: Pos) -> bool {
+        // true if there is no square to go to beyond pos in this direction, only walls or the board's bound
+        let mut curr_pos = pos + dir;
+        while let Some(square) = self.get(curr_pos) {
+            if *square != Square::Wall {
+                return false;
+            }
+            curr_pos = curr_pos + dir;
+        }
+        true
+    }
+
+    pub fn king_pos(&self, color: Color) -> Option<Pos> {
         for (i, square) in self.squares.iter().enumerate() {
-            if let Some((piece_color, piece)) = square {
+            if let Square::Piece(piece_color, piece) = square {
                 if *piece_color == color && *piece == Piece::King {
                     return Some(self.pos(i));
                 }
@@ -56,132 +264,634 @@ impl Board {
         None
     }
 
-    fn is_checked(&self, color: Color) -> bool {
-        // if this panic then there's no king of this color on the board lol
-        let king_pos = self.king_pos(color).unwrap();
-        let o_color = color.next();
-        for (_, actions) in self.moves(o_color, false) {
-            for action in actions {
-                if let Action::Go(go_pos) = action {
-                    if go_pos == king_pos {
-                        return true;
-                    }
-                } else if let Action::Take(take_pos) = action {
-                    if take_pos == king_pos {
-                        return true;
+    pub fn is_attacked(&self, pos: Pos, color: Color) -> bool {
+        // true if a piece of color could capture on pos,
+        // looking outwards from pos rather than generating every move of color
+        let i = self.i(pos);
+        let terrain = self.terrain[i];
+        let attacker = |j: usize| match self.squares[j] {
+            Square::Piece(p_color, piece) if p_color == color => Some(piece),
+            _ => None,
+        };
+        // knights and kings can enter any terrain
+        if self
+            .attacks
+            .knight(i)
+            .iter()
+            .any(|j| attacker(*j) == Some(Piece::Knight))
+        {
+            return true;
+        }
+        for (dir_i, dir) in LOS.iter().enumerate() {
+            let ray = self.attacks.ray(i, dir_i);
+            match ray.first().and_then(|j| attacker(*j)) {
+                Some(Piece::King) => return true,
+                // pawns capture beside the square in front of them, but not on fortifications
+                Some(pawn @ Piece::Pawn { orientation, .. })
+                    if orientation.neighbors().contains(&(*dir * -1))
+                        && pawn.can_enter(terrain)
+                        && terrain != Terrain::Fortification =>
+                {
+                    return true
+                }
+                _ => {}
+            }
+            // the first piece along the line, if the squares before it can be crossed
+            for j in ray {
+                match self.squares[*j] {
+                    Square::Empty if Piece::Queen.can_enter(self.terrain[*j]) => continue,
+                    Square::Piece(p_color, piece) if p_color == color => {
+                        // LOS starts with the 4 lines and ends with the 4 diagonals
+                        let slides = match piece {
+                            Piece::Queen => true,
+                            Piece::Rook => dir_i < 4,
+                            Piece::Bishop => dir_i >= 4,
+                            _ => false,
+                        };
+                        if slides && piece.can_enter(terrain) {
+                            return true;
+                        }
                     }
+                    _ => {}
                 }
+                break;
             }
         }
         false
     }
 
-    pub fn moves(&self, color: Color, safe_moves: bool) -> Vec<(Pos, Vec<Action>)> {
+    pub fn is_checked(&self, color: Color) -> bool {
+        // a side without a king can't be checked
+        match self.king_pos(color) {
+            Some(king_pos) => self.is_attacked(king_pos, color.next()),
+            None => false,
+        }
+    }
+
+    pub fn lost_king(&self, color: Color) -> bool {
+        // the king of color is gone (to lava for example), boards without kings at all are played to the end
+        self.king_pos(color).is_none() && self.king_pos(color.next()).is_some()
+    }
+
+    pub fn moves(&self, color: Color, safe_moves: bool) -> MoveList {
         // generate all moves for color
-        let mut res = Vec::new();
+        let mut res = MoveList::new();
         for (i, square) in self.squares.iter().enumerate() {
-            if let Some((piece_color, piece)) = square {
+            if let Square::Piece(piece_color, piece) = square {
                 if *piece_color == color {
-                    let pos = self.pos(i);
-                    let mut p_moves = piece.moves(self, pos, color);
-                    if safe_moves {
-                        p_moves = p_moves
-                            .into_iter()
-                            .filter(|actions| {
-                                let board = self.play(color, pos, actions);
-                                !board.is_checked(color)
-                            })
-                            .collect();
-                    }
-                    for p_move in p_moves {
-                        res.push((pos, p_move));
-                    }
+                    piece.moves(self, self.pos(i), color, &mut res);
                 }
             }
         }
+        if safe_moves {
+            // a move can't leave the king in check, nor lose it
+            let has_king = self.king_pos(color).is_some();
+            res.retain(|mv| {
+                let after = self.play(color, *mv);
+                !after.is_checked(color) && (!has_king || after.king_pos(color).is_some())
+            });
+        }
         res
     }
 
+    pub fn insufficient_material(&self) -> bool {
+        // neither side can ever mate with only kings, a single minor piece,
+        // or bishops that all live on the same square color
+        let mut minors = 0;
+        let mut bishop_square_colors = Vec::new();
+        for (i, square) in self.squares.iter().enumerate() {
+            if let Square::Piece(_, piece) = square {
+                match piece {
+                    Piece::King => {}
+                    Piece::Knight => minors += 1,
+                    Piece::Bishop => {
+                        minors += 1;
+                        let pos = self.pos(i);
+                        bishop_square_colors.push((pos.0 + pos.1) % 2);
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        minors <= 1
+            || (bishop_square_colors.len() == minors
+                && bishop_square_colors
+                    .iter()
+                    .all(|c| *c == bishop_square_colors[0]))
+    }
+
+    pub fn outcome(&self, color: Color) -> Option<Outcome> {
+        // the outcomes that can be read from the position alone, color being the side to move
+        if self.lost_king(color) {
+            return Some(Outcome::Checkmate(color.next()));
+        }
+        if self.moves(color, true).is_empty() {
+            if self.is_checked(color) {
+                return Some(Outcome::Checkmate(color.next()));
+            }
+            return Some(Outcome::Stalemate);
+        }
+        if self.insufficient_material() {
+            return Some(Outcome::InsufficientMaterial);
+        }
+        None
+    }
+
     fn begin_turn(&mut self, color: Color) {
         for i in 0..self.squares.len() {
-            if let Some((p_color, piece)) = self.squares[i] {
+            if let Square::Piece(p_color, piece) = self.squares[i] {
                 if p_color == color {
-                    self.squares[i] = Some((p_color, piece.begin_turn()))
+                    self.set(self.pos(i), Square::Piece(p_color, piece.begin_turn()))
                 }
             }
         }
     }
 
     fn moved(&mut self, start: Pos, target: Pos) {
-        let (color, piece) = self.get(target).unwrap().unwrap();
-        self.set(target, Some((color, piece.moved(start, target))));
+        let (color, piece) = self.get(target).unwrap().piece().unwrap();
+        self.set(target, Square::Piece(color, piece.moved(start, target)));
     }
 
-    pub fn play(&self, color: Color, pos: Pos, actions: &Vec<Action>) -> Self {
-        // TODO: send the appropriate events to update pieces data
+    pub fn play(&self, color: Color, mv: Move) -> Self {
         let mut res = self.clone();
         res.begin_turn(color);
-        let mut last_pos = pos;
         // we unwrap because no move can be played out of the board's bound
-        let square = self.get(pos).unwrap();
-        for action in actions {
-            match action {
-                Action::Go(go_pos) => {
-                    res.set(last_pos, None);
-                    res.set(*go_pos, *square);
-                    res.moved(last_pos, *go_pos);
-                    last_pos = *go_pos;
+        let square = *self.get(mv.from()).unwrap();
+        res.set(mv.from(), Square::Empty);
+        res.set(mv.to(), square);
+        // the unit follows its piece, a promoted piece is still the same unit
+        res.set_unit(mv.to(), self.unit(mv.from()));
+        res.moved(mv.from(), mv.to());
+        if let Some(take_pos) = mv.take() {
+            res.set(take_pos, Square::Empty);
+        }
+        if let Some(piece) = mv.promotion() {
+            res.set(mv.to(), Square::Piece(color, piece));
+        }
+        res.terrain_effects(mv.to());
+        res
+    }
+
+    fn terrain_effects(&mut self, pos: Pos) {
+        // apply the effects of the terrain on a piece that just ended its move on pos
+        let square = *self.get(pos).unwrap();
+        match self.terrain(pos) {
+            Terrain::Lava => self.set(pos, Square::Empty),
+            Terrain::Teleporter(link_pos) => {
+                if let Some(Square::Empty) = self.get(link_pos) {
+                    let unit = self.unit(pos);
+                    self.set(pos, Square::Empty);
+                    self.set(link_pos, square);
+                    self.set_unit(link_pos, unit);
                 }
-                Action::Take(take_pos) => res.set(*take_pos, None),
-                Action::Promotion(piece) => {
-                    let (color, _) = square.unwrap();
-                    res.set(last_pos, Some((color, *piece)));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn cell(&self, pos: Pos) -> String {
+        // how a square is drawn, always 2 characters wide
+        match self.get(pos).unwrap() {
+            Square::Piece(color, piece) => format!(
+                "{} ",
+                match color {
+                    Color::White => match piece {
+                        Piece::Pawn {
+                            orientation: _,
+                            status: _,
+                        } => "♙",
+                        Piece::Knight => "♘",
+                        Piece::Bishop => "♗",
+                        Piece::Rook => "♖",
+                        Piece::Queen => "♕",
+                        Piece::King => "♔",
+                    },
+                    Color::Black => match piece {
+                        Piece::Pawn {
+                            orientation: _,
+                            status: _,
+                        } => "♟︎",
+                        Piece::Knight => "♞",
+                        Piece::Bishop => "♝",
+                        Piece::Rook => "♜",
+                        Piece::Queen => "♛",
+                        Piece::King => "♚",
+                    },
                 }
-            };
+            ),
+            Square::Wall => "██".to_string(),
+            Square::Empty => match self.terrain(pos) {
+                Terrain::Floor => "  ",
+                Terrain::Ice => "░░",
+                Terrain::Lava => "▓▓",
+                Terrain::Water => "≈≈",
+                Terrain::Teleporter(_) => "◎ ",
+                Terrain::Fortification => "▣ ",
+            }
+            .to_string(),
         }
-        res
     }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (i, square) in self.squares.iter().enumerate() {
+        for i in 0..self.squares.len() {
             if i % self.width == 0 && i != 0 {
-                write!(f, "\n")?;
-            }
-            if let Some((color, piece)) = square {
-                write!(
-                    f,
-                    "{} ",
-                    match color {
-                        Color::White => match piece {
-                            Piece::Pawn {
-                                orientation: _,
-                                status: _,
-                            } => "♙",
-                            Piece::Knight => "♘",
-                            Piece::Bishop => "♗",
-                            Piece::Rook => "♖",
-                            Piece::Queen => "♕",
-                            Piece::King => "♔",
-                        },
-                        Color::Black => match piece {
-                            Piece::Pawn {
-                                orientation: _,
-                                status: _,
-                            } => "♟︎",
-                            Piece::Knight => "♞",
-                            Piece::Bishop => "♝",
-                            Piece::Rook => "♜",
-                            Piece::Queen => "♛",
-                            Piece::King => "♚",
+                writeln!(f)?;
+            }
+            write!(f, "{}", self.cell(self.pos(i)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        board::{Board, Square, Terrain},
+        fen::fen2board,
+        game::{standard_board, Game, Outcome},
+        moves::{Move, MoveList},
+        piece::{Color, PawnStatus, Piece},
+        pos::{Pos, LOS},
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    pub fn random_dims(rng: &mut StdRng) -> (usize, usize) {
+        (rng.gen_range(3..=26), rng.gen_range(3..=26))
+    }
+
+    pub fn random_board(rng: &mut StdRng, width: usize, height: usize) -> Board {
+        // a random board with exactly one king per color and a few other pieces
+        let mut board = Board::new(width, height);
+        let mut free: Vec<usize> = (0..width * height).collect();
+        for color in [Color::White, Color::Black] {
+            let count = rng.gen_range(1..=free.len().min(8) / 2);
+            for n in 0..count {
+                let i = free.swap_remove(rng.gen_range(0..free.len()));
+                let piece = if n == 0 {
+                    Piece::King
+                } else {
+                    match rng.gen_range(0..5) {
+                        0 => Piece::Pawn {
+                            orientation: LOS[rng.gen_range(0..4)],
+                            status: PawnStatus::CanLeap,
                         },
+                        1 => Piece::Knight,
+                        2 => Piece::Bishop,
+                        3 => Piece::Rook,
+                        _ => Piece::Queen,
                     }
-                )?;
-            } else {
-                write!(f, "  ")?;
+                };
+                board.set(board.pos(i), Square::Piece(color, piece));
             }
         }
-        Ok(())
+        board
+    }
+
+    #[test]
+    fn index_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let board = Board::new(width, height);
+            for i in 0..width * height {
+                let pos = board.pos(i);
+                assert!(board.get(pos).is_some());
+                assert!(board.i(pos) == i);
+            }
+            assert!(board.get(Pos(width as i32, 0)).is_none());
+            assert!(board.get(Pos(0, height as i32)).is_none());
+            assert!(board.get(Pos(-1, 0)).is_none());
+        }
+    }
+
+    #[test]
+    fn moves_stay_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let board = random_board(&mut rng, width, height);
+            for color in [Color::White, Color::Black] {
+                for mv in board.moves(color, true).iter() {
+                    let after = board.play(color, *mv);
+                    assert!(after.squares().len() == width * height);
+                    assert!(!after.is_checked(color));
+                    let pieces =
+                        |b: &Board| b.squares().iter().filter(|s| s.piece().is_some()).count();
+                    assert!(pieces(&after) <= pieces(&board));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn attacks_match_moves() {
+        // the attack tables find exactly the captures of move generation, whatever the terrain
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let mut board = random_board(&mut rng, width, height);
+            for i in 0..width * height {
+                let pos = board.pos(i);
+                match rng.gen_range(0..10) {
+                    0 => board.set_terrain(pos, Terrain::Ice),
+                    1 => board.set_terrain(pos, Terrain::Water),
+                    2 => board.set_terrain(pos, Terrain::Fortification),
+                    3 if board.get(pos) == Some(&Square::Empty) => board.set(pos, Square::Wall),
+                    // more pawns, facing any direction
+                    4 | 5 if board.get(pos) == Some(&Square::Empty) => {
+                        let color = [Color::White, Color::Black][rng.gen_range(0..2)];
+                        let orientation = LOS[rng.gen_range(0..8)];
+                        let status = PawnStatus::CannotLeap;
+                        board.set(
+                            pos,
+                            Square::Piece(
+                                color,
+                                Piece::Pawn {
+                                    orientation,
+                                    status,
+                                },
+                            ),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            for color in [Color::White, Color::Black] {
+                let moves = board.moves(color, false);
+                for i in 0..width * height {
+                    let pos = board.pos(i);
+                    if !matches!(board.get(pos), Some(Square::Piece(p_color, _)) if *p_color != color)
+                    {
+                        continue;
+                    }
+                    let captured = moves.iter().any(|mv| mv.to() == pos);
+                    assert!(board.is_attacked(pos, color) == captured);
+                }
+            }
+        }
+    }
+
+    fn rebuild(board: &Board) -> Board {
+        // the same board built from scratch, to check the incremental hash
+        let mut rebuilt = Board::new(board.width, board.height);
+        for (i, square) in board.squares().iter().enumerate() {
+            rebuilt.set(board.pos(i), *square);
+        }
+        rebuilt
+    }
+
+    #[test]
+    fn zobrist_hash() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            let (width, height) = random_dims(&mut rng);
+            let mut board = random_board(&mut rng, width, height);
+            let mut color = Color::White;
+            for _ in 0..10 {
+                let moves = board.moves(color, true);
+                if moves.is_empty() {
+                    break;
+                }
+                let mv = moves[rng.gen_range(0..moves.len())];
+                board = board.play(color, mv);
+                color = color.next();
+                assert!(board.hash(color) == rebuild(&board).hash(color));
+                assert!(board.hash(Color::White) != board.hash(Color::Black));
+            }
+        }
+        // the same position reached through different move orders
+        let knight = |board: &Board, color, from, to| board.play(color, Move::new(from, to));
+        let board = standard_board();
+        let board1 = knight(&board, Color::White, Pos(6, 7), Pos(5, 5));
+        let board1 = knight(&board1, Color::Black, Pos(6, 0), Pos(5, 2));
+        let board1 = knight(&board1, Color::White, Pos(1, 7), Pos(2, 5));
+        let board2 = knight(&board, Color::White, Pos(1, 7), Pos(2, 5));
+        let board2 = knight(&board2, Color::Black, Pos(6, 0), Pos(5, 2));
+        let board2 = knight(&board2, Color::White, Pos(6, 7), Pos(5, 5));
+        assert!(board1.hash(Color::Black) == board2.hash(Color::Black));
+        assert!(board1.hash(Color::Black) != board.hash(Color::Black));
+    }
+
+    #[test]
+    fn display_dims() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let board = random_board(&mut rng, width, height);
+            let display = format!("{}", board);
+            assert!(display.lines().count() == height);
+            for line in display.lines() {
+                // every square is rendered as a 2 character cell
+                assert!(line.chars().filter(|c| *c != '\u{fe0e}').count() == width * 2);
+            }
+        }
+    }
+
+    fn destinations(board: &Board, pos: Pos) -> Vec<Pos> {
+        let (color, piece) = board.get(pos).unwrap().piece().unwrap();
+        let mut moves = MoveList::new();
+        piece.moves(board, pos, color, &mut moves);
+        moves.iter().map(|mv| mv.to()).collect()
+    }
+
+    #[test]
+    fn walls() {
+        // an L-shaped room
+        let mut board = Board::from_layout(
+            "
+            ..##
+            ..##
+            ....
+            ....
+            ",
+        );
+        assert!(board.width == 4 && board.height == 4);
+        assert!(board.get(Pos(2, 0)) == Some(&Square::Wall));
+        assert!(board.get(Pos(1, 1)) == Some(&Square::Empty));
+        assert!(board.get(Pos(4, 0)).is_none());
+        // sliding pieces stop at walls
+        board.set(Pos(2, 3), Square::Piece(Color::White, Piece::Rook));
+        let rook_moves = destinations(&board, Pos(2, 3));
+        assert!(rook_moves.len() == 4);
+        assert!(rook_moves.contains(&Pos(2, 2)) && !rook_moves.contains(&Pos(2, 1)));
+        // knights jump over them but can't land on them
+        board.set(Pos(2, 3), Square::Empty);
+        board.set(Pos(3, 3), Square::Piece(Color::White, Piece::Knight));
+        board.set(Pos(3, 2), Square::Wall);
+        board.set(Pos(2, 2), Square::Wall);
+        let knight_moves = destinations(&board, Pos(3, 3));
+        assert!(knight_moves == vec![Pos(1, 2)]);
+    }
+
+    #[test]
+    fn pawns_and_walls() {
+        let pawn = Square::Piece(
+            Color::White,
+            Piece::Pawn {
+                orientation: Pos(0, -1),
+                status: PawnStatus::CanLeap,
+            },
+        );
+        // reaching the wall that closes an L-shaped room promotes
+        let mut board = Board::from_layout(
+            "
+            ..##
+            ..##
+            ....
+            ....
+            ",
+        );
+        board.set(Pos(2, 3), pawn);
+        let mut moves = MoveList::new();
+        pawn.piece()
+            .unwrap()
+            .1
+            .moves(&board, Pos(2, 3), Color::White, &mut moves);
+        assert!(moves.len() == 2);
+        assert!(moves.contains(&Move::new(Pos(2, 3), Pos(2, 2)).promoting(Piece::Queen)));
+        // but a pillar only blocks the pawn
+        let mut board = Board::from_layout(
+            "
+            ...
+            .#.
+            ...
+            ...
+            ",
+        );
+        board.set(Pos(1, 3), pawn);
+        assert!(destinations(&board, Pos(1, 3)) == vec![Pos(1, 2)]);
+        board.set(Pos(1, 2), pawn);
+        assert!(destinations(&board, Pos(1, 2)).is_empty());
+    }
+
+    #[test]
+    fn cross_room_display() {
+        let board = Board::from_layout(
+            "
+            #.#
+            ...
+            #.#
+            ",
+        );
+        assert!(format!("{}", board) == "██  ██\n      \n██  ██");
+    }
+
+    #[test]
+    fn ice_and_water() {
+        let mut board = Board::from_layout(
+            "
+            ..*..
+            .....
+            ~~~~~
+            .....
+            ",
+        );
+        board.set(Pos(0, 0), Square::Piece(Color::White, Piece::Rook));
+        // the rook glides over the ice and is stopped by the water
+        let rook_moves = destinations(&board, Pos(0, 0));
+        assert!(rook_moves.contains(&Pos(3, 0)) && !rook_moves.contains(&Pos(2, 0)));
+        assert!(rook_moves.contains(&Pos(0, 1)) && !rook_moves.contains(&Pos(0, 2)));
+        // but a knight can jump in it
+        board.set(Pos(1, 0), Square::Piece(Color::White, Piece::Knight));
+        assert!(destinations(&board, Pos(1, 0)).contains(&Pos(0, 2)));
+        // and pawns can't
+        board.set(
+            Pos(4, 1),
+            Square::Piece(
+                Color::Black,
+                Piece::Pawn {
+                    orientation: Pos(0, 1),
+                    status: PawnStatus::CanLeap,
+                },
+            ),
+        );
+        assert!(destinations(&board, Pos(4, 1)).is_empty());
+    }
+
+    #[test]
+    fn lava_and_teleporters() {
+        let mut board = Board::from_layout(
+            "
+            1..!
+            ....
+            ...1
+            ",
+        );
+        board.set(Pos(0, 1), Square::Piece(Color::White, Piece::Rook));
+        let after = board.play(Color::White, Move::new(Pos(0, 1), Pos(0, 0)));
+        assert!(after.get(Pos(0, 0)) == Some(&Square::Empty));
+        assert!(after.get(Pos(3, 2)) == Some(&Square::Piece(Color::White, Piece::Rook)));
+        let after = after.play(Color::White, Move::new(Pos(3, 2), Pos(3, 0)));
+        assert!(after
+            .squares()
+            .iter()
+            .all(|square| *square == Square::Empty));
+        // the king can't walk into lava
+        board.set(Pos(2, 1), Square::Piece(Color::White, Piece::King));
+        board.set(Pos(0, 2), Square::Piece(Color::Black, Piece::King));
+        assert!(!board
+            .moves(Color::White, true)
+            .iter()
+            .any(|mv| mv.to() == Pos(3, 0)));
+    }
+
+    #[test]
+    fn kingless() {
+        // without kings nobody is ever in check and the game plays on
+        let (board, color) = fen2board("8/8/8/8/8/8/4P3/8 w - - 0 1").unwrap();
+        assert!(board.moves(color, true).len() == 2);
+        let (board, color) = fen2board("r7/4p3/8/8/8/8/4P3/7R w - - 0 1").unwrap();
+        assert!(!board.is_checked(color) && !board.lost_king(color));
+        let mut game = Game::new(board, color);
+        for _ in 0..6 {
+            assert!(game.outcome().is_none());
+            let mv = game.legal_moves()[0];
+            game.make_move(mv);
+        }
+        // but a side that lost its king has lost the game
+        let (board, color) = fen2board("8/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        assert!(board.lost_king(color) && !board.lost_king(color.next()));
+        assert!(board.outcome(color) == Some(Outcome::Checkmate(Color::White)));
+    }
+
+    #[test]
+    fn fortifications() {
+        let mut board = Board::from_layout(
+            "
+            .+.
+            ...
+            ",
+        );
+        board.set(Pos(1, 0), Square::Piece(Color::Black, Piece::Knight));
+        board.set(
+            Pos(0, 1),
+            Square::Piece(
+                Color::White,
+                Piece::Pawn {
+                    orientation: Pos(0, -1),
+                    status: PawnStatus::CannotLeap,
+                },
+            ),
+        );
+        assert!(!destinations(&board, Pos(0, 1)).contains(&Pos(1, 0)));
+        // other pieces still can
+        board.set(Pos(2, 1), Square::Piece(Color::White, Piece::Bishop));
+        assert!(destinations(&board, Pos(2, 1)).contains(&Pos(1, 0)));
+    }
+
+    #[test]
+    fn terrain_display() {
+        let board = Board::from_layout("*!~+1#1");
+        assert!(format!("{}", board) == "░░▓▓≈≈▣ ◎ ██◎ ");
+        assert!(board.terrain(Pos(4, 0)) == Terrain::Teleporter(Pos(6, 0)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_wide() {
+        // the 27th file would have no letter
+        Board::from_layout(&".".repeat(27));
     }
 }
@@ -0,0 +1,330 @@
+// Interactive play against the AI in a terminal
+use crate::ai::{minmax, TABLE_SIZE};
+use crate::board::Board;
+use crate::eval::{Evaluator, MaterialEvaluator};
+use crate::fen::fen2game;
+use crate::game::{standard_board, Game};
+use crate::moves::Move;
+use crate::pgn::{game2pgn, line2san, move2san, pgn2game, result2pgn, san2move};
+use crate::piece::Color;
+use crate::pos::Pos;
+use crate::profile::AiProfile;
+use crate::transposition::TranspositionTable;
+use crate::uci::uci2move;
+use rand::{rngs::StdRng, SeedableRng};
+use std::io::{self, BufRead, Write};
+
+const HIGHLIGHT: &str = "\x1b[43m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str = "Enter moves like e2e4 or Nf3. Commands:
+  undo          take back your last move
+  hint          ask the AI for a move
+  resign        give up the game
+  new           start a new game
+  depth <n>     set the AI's search depth
+  save <file>   save the game as PGN
+  load <file>   load a game from a PGN or FEN file
+  help          show this message
+  quit          leave";
+
+pub fn render(board: &Board, highlights: &[Pos]) -> String {
+    // the board with rank and file labels, highlighting some squares
+    let rank_width = board.height.to_string().len();
+    let mut res = String::new();
+    for y in 0..board.height {
+        res += &format!("{:>width$} ", board.height - y, width = rank_width);
+        for x in 0..board.width {
+            let pos = Pos(x as i32, y as i32);
+            if highlights.contains(&pos) {
+                res += &format!("{}{}{}", HIGHLIGHT, board.cell(pos), RESET);
+            } else {
+                res += &board.cell(pos);
+            }
+        }
+        res += "\n";
+    }
+    res += &" ".repeat(rank_width + 1);
+    for x in 0..board.width {
+        res += &format!("{} ", (b'a' + x as u8) as char);
+    }
+    res
+}
+
+pub struct Session<W: Write> {
+    output: W,
+    pub game: Game,
+    pub human: Color,
+    pub depth: u32,
+    pub evaluator: Box<dyn Evaluator>,
+    // what the searches with depth and evaluator learned, kept from one move to the next
+    table: TranspositionTable,
+    // when set the AI plays with this profile instead of depth and evaluator, hints still use them
+    pub profile: Option<AiProfile>,
+    // every random decision of the session comes from here, so a game can be replayed from its seed
+    pub rng: StdRng,
+    // set when the human resigned, since the game itself is not over
+    resigned: bool,
+}
+
+impl<W: Write> Session<W> {
+    pub fn new(output: W, human: Color, depth: u32) -> Self {
+        Self {
+            output,
+            game: Game::new(standard_board(), Color::White),
+            human,
+            depth,
+            evaluator: Box::new(MaterialEvaluator),
+            table: TranspositionTable::new(TABLE_SIZE),
+            profile: None,
+            rng: StdRng::seed_from_u64(0),
+            resigned: false,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.resigned || self.game.outcome().is_some()
+    }
+
+    fn show(&mut self) -> io::Result<()> {
+        let highlights = match self.game.last_move() {
+            Some(mv) => vec![mv.from(), mv.to()],
+            None => Vec::new(),
+        };
+        writeln!(self.output, "{}", render(&self.game.board, &highlights))?;
+        if let Some(outcome) = self.game.outcome() {
+            writeln!(self.output, "{} ({})", outcome, result2pgn(Some(outcome)))?;
+        } else if self.game.board.is_checked(self.game.player) {
+            writeln!(self.output, "Check!")?;
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, mv: Move) -> io::Result<()> {
+        let san = move2san(&self.game.board, self.game.player, mv);
+        let who = if self.game.player == self.human {
+            "You play"
+        } else {
+            "The AI plays"
+        };
+        writeln!(self.output, "{} {}", who, san)?;
+        self.game.make_move(mv);
+        Ok(())
+    }
+
+    fn ai_turn(&mut self) -> io::Result<()> {
+        // let the AI play until it's the human's turn
+        while !self.is_over() && self.game.player != self.human {
+            if let Some(profile) = &self.profile {
+                let mv = profile
+                    .choose_move(&self.game.board, self.game.player, &mut self.rng)
+                    .unwrap();
+                self.play(mv)?;
+                continue;
+            }
+            let result = minmax(
+                &self.game.board,
+                self.game.player,
+                self.depth,
+                &self.evaluator,
+                &self.table,
+            )
+            .unwrap();
+            // the rest of the principal variation is what the AI expects to happen next
+            let plan = line2san(&self.game.board, self.game.player, &result.pv);
+            self.play(result.best_move)?;
+            if plan.len() > 1 {
+                writeln!(self.output, "The AI is planning {}", plan[1..].join(" "))?;
+            }
+        }
+        self.show()
+    }
+
+    fn read_move(&self, text: &str) -> Option<Move> {
+        // moves can be given in coordinates or in SAN
+        uci2move(&self.game.board, self.game.player, text)
+            .or_else(|| san2move(&self.game.board, self.game.player, text).ok())
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        self.game = match fen2game(text.trim()) {
+            Ok(game) => game,
+            Err(_) => pgn2game(&text).map_err(|err| err.to_string())?.game,
+        };
+        self.resigned = false;
+        Ok(())
+    }
+
+    pub fn handle(&mut self, line: &str) -> io::Result<bool> {
+        // handle a line typed by the human, returns false when it's time to quit
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => return Ok(false),
+            ["help"] => writeln!(self.output, "{}", HELP)?,
+            ["new"] => {
+                self.game = Game::new(standard_board(), Color::White);
+                self.resigned = false;
+                self.ai_turn()?;
+            }
+            ["undo"] => {
+                // take back the AI's answer along with the human's move
+                self.resigned = false;
+                while self.game.undo().is_some() && self.game.player != self.human {}
+                self.show()?;
+            }
+            ["hint"] => {
+                if self.is_over() {
+                    writeln!(self.output, "The game is over")?;
+                } else {
+                    let result = minmax(
+                        &self.game.board,
+                        self.game.player,
+                        self.depth,
+                        &self.evaluator,
+                        &self.table,
+                    )
+                    .unwrap();
+                    let line = line2san(&self.game.board, self.game.player, &result.pv);
+                    let san = move2san(&self.game.board, self.game.player, result.best_move);
+                    writeln!(
+                        self.output,
+                        "Hint: {} (score {:+.2}, line {})",
+                        san,
+                        result.score,
+                        line.join(" ")
+                    )?;
+                }
+            }
+            ["resign"] => {
+                if !self.is_over() {
+                    self.resigned = true;
+                    let result = match self.human {
+                        Color::White => "0-1",
+                        Color::Black => "1-0",
+                    };
+                    writeln!(self.output, "You resigned ({})", result)?;
+                }
+            }
+            ["depth", depth] => match depth.parse::<u32>() {
+                Ok(depth) if depth > 0 => {
+                    self.depth = depth;
+                    writeln!(self.output, "The AI now searches {} plies deep", depth)?;
+                }
+                _ => writeln!(self.output, "The depth must be a positive number")?,
+            },
+            ["save", path] => {
+                let pgn = game2pgn(&self.game, &[]);
+                match std::fs::write(path, pgn) {
+                    Ok(()) => writeln!(self.output, "Game saved to {}", path)?,
+                    Err(err) => writeln!(self.output, "Could not save the game: {}", err)?,
+                }
+            }
+            ["load", path] => match self.load(path) {
+                Ok(()) => {
+                    writeln!(self.output, "Game loaded from {}", path)?;
+                    self.ai_turn()?;
+                }
+                Err(err) => writeln!(self.output, "Could not load the game: {}", err)?,
+            },
+            [text] => {
+                if self.is_over() {
+                    writeln!(self.output, "The game is over, type new to play again")?;
+                } else if let Some(mv) = self.read_move(text) {
+                    self.play(mv)?;
+                    self.ai_turn()?;
+                } else {
+                    writeln!(
+                        self.output,
+                        "{} is not a legal move, type help for the commands",
+                        text
+                    )?;
+                }
+            }
+            _ => writeln!(self.output, "Unknown command, type help for the commands")?,
+        }
+        Ok(true)
+    }
+
+    pub fn run(&mut self, input: impl BufRead) -> io::Result<()> {
+        writeln!(self.output, "{}", HELP)?;
+        self.ai_turn()?;
+        for line in input.lines() {
+            if !self.handle(&line?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cli::{render, Session},
+        game::standard_board,
+        piece::Color,
+        pos::Pos,
+    };
+
+    fn run_script(script: &str, human: Color) -> (String, Session<Vec<u8>>) {
+        let mut session = Session::new(Vec::new(), human, 1);
+        session.run(script.as_bytes()).unwrap();
+        let output = String::from_utf8(std::mem::take(&mut session.output)).unwrap();
+        (output, session)
+    }
+
+    #[test]
+    fn labels_and_highlights() {
+        let rendered = render(&standard_board(), &[Pos(4, 6)]);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert!(lines.len() == 9);
+        assert!(lines[0].starts_with("8 ♜ "));
+        assert!(lines[6].contains("\x1b[43m♙ \x1b[0m"));
+        assert!(lines[8] == "  a b c d e f g h ");
+    }
+
+    #[test]
+    fn play_and_undo() {
+        let (output, session) = run_script("e2e4\nNf3\nundo\nhint\nfoo\n", Color::White);
+        assert!(output.contains("You play e4\nThe AI plays "));
+        assert!(output.contains("You play Nf3\n"));
+        assert!(output.contains("Hint: "));
+        assert!(output.contains("line "));
+        assert!(output.contains("foo is not a legal move"));
+        assert!(session.game.history.len() == 2);
+    }
+
+    #[test]
+    fn ai_opens_for_black() {
+        let (output, session) = run_script("depth 2\nresign\ne5\n", Color::Black);
+        assert!(output.contains("The AI plays "));
+        assert!(output.contains("The AI now searches 2 plies deep"));
+        assert!(output.contains("You resigned (1-0)"));
+        assert!(output.contains("The game is over"));
+        assert!(session.game.history.len() == 1);
+    }
+
+    #[test]
+    fn ai_plan() {
+        let (output, _) = run_script("depth 2\ne4\n", Color::White);
+        assert!(output.contains("The AI is planning "));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("dungeon_chess_{}.pgn", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (_, saved) = run_script(&format!("e4\nd4\nsave {}\n", path), Color::White);
+        let (output, loaded) = run_script(&format!("load {}\nquit\n", path), Color::White);
+        assert!(output.contains(&format!("Game loaded from {}", path)));
+        assert!(loaded.game.board == saved.game.board);
+        std::fs::write(path, "7k/8/8/8/8/8/8/K6R w - - 0 1").unwrap();
+        let (_, loaded) = run_script(&format!("load {}\nRh2\n", path), Color::White);
+        assert!(loaded.game.history.len() == 2);
+        std::fs::remove_file(path).unwrap();
+        let (output, _) = run_script(&format!("load {}\n", path), Color::White);
+        assert!(output.contains("Could not load the game"));
+    }
+}
@@ -0,0 +1,602 @@
+// The deployment phase before a match: each side places its army in its zone of the room, then the game starts
+use crate::board::{Board, Square, Terrain};
+use crate::game::Game;
+use crate::piece::{Color, PawnStatus, Piece};
+use crate::pos::Pos;
+use crate::unit::UnitId;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt::Display;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Zone {
+    pub squares: Vec<Pos>,
+    // the direction of the enemy, that pawns face once deployed
+    pub forward: Pos,
+}
+
+impl Zone {
+    pub fn rows(board: &Board, color: Color, depth: usize) -> Self {
+        // the first rows on the side of color, white deploys at the bottom like in chess
+        let depth = depth.min(board.height);
+        let (rows, forward) = match color {
+            Color::White => (board.height - depth..board.height, Pos(0, -1)),
+            Color::Black => (0..depth, Pos(0, 1)),
+        };
+        let squares = rows
+            .flat_map(|y| (0..board.width).map(move |x| Pos(x as i32, y as i32)))
+            .filter(|pos| board.get(*pos) != Some(&Square::Wall))
+            .collect();
+        Self { squares, forward }
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.squares.contains(&pos)
+    }
+
+    fn depth(&self, pos: Pos) -> i32 {
+        // how far forward a square is, the back of the zone is the lowest
+        pos.0 * self.forward.0 + pos.1 * self.forward.1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Recruit {
+    pub piece: Piece,
+    // the unit of the roster the piece is, None for a piece that only lives for one fight
+    pub unit: Option<UnitId>,
+}
+
+impl From<Piece> for Recruit {
+    fn from(piece: Piece) -> Self {
+        Self { piece, unit: None }
+    }
+}
+
+pub fn standard_army() -> Vec<Piece> {
+    // the pieces of a chess player, pawns are turned to face the enemy once deployed
+    let mut army = vec![
+        Piece::Rook,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Queen,
+        Piece::King,
+        Piece::Bishop,
+        Piece::Knight,
+        Piece::Rook,
+    ];
+    army.extend(
+        [Piece::Pawn {
+            orientation: Pos(0, -1),
+            status: PawnStatus::CanLeap,
+        }; 8],
+    );
+    army
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DeployError {
+    KingCount { color: Color, count: usize },
+    OutsideZone(Pos),
+    Occupied(Pos),
+    // the piece can't stand on the terrain of this square
+    Terrain(Pos),
+    NotInArmy(Piece),
+    UnitNotInArmy(UnitId),
+    NothingToRemove(Pos),
+    // there is no square left where the piece can go
+    ZoneFull(Piece),
+    Unplaced { color: Color, count: usize },
+}
+
+impl Display for DeployError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeployError::KingCount { color, count } => {
+                write!(f, "{:?} needs exactly one king, found {}", color, count)
+            }
+            DeployError::OutsideZone(pos) => {
+                write!(f, "({}, {}) is outside the deployment zone", pos.0, pos.1)
+            }
+            DeployError::Occupied(pos) => write!(f, "({}, {}) is not free", pos.0, pos.1),
+            DeployError::Terrain(pos) => {
+                write!(
+                    f,
+                    "the piece can't stand on the terrain of ({}, {})",
+                    pos.0, pos.1
+                )
+            }
+            DeployError::NotInArmy(piece) => write!(f, "no {:?} is left to place", piece),
+            DeployError::UnitNotInArmy(unit) => {
+                write!(f, "unit {} is not left to place", unit.0)
+            }
+            DeployError::NothingToRemove(pos) => {
+                write!(f, "there is no piece of yours on ({}, {})", pos.0, pos.1)
+            }
+            DeployError::ZoneFull(piece) => write!(f, "there is no room left for a {:?}", piece),
+            DeployError::Unplaced { color, count } => {
+                write!(f, "{:?} still has {} pieces to place", color, count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeployError {}
+
+#[derive(Clone)]
+struct Side {
+    zone: Zone,
+    // the pieces that are still to be placed
+    army: Vec<Recruit>,
+}
+
+#[derive(Clone)]
+pub struct Deployment {
+    board: Board,
+    white: Side,
+    black: Side,
+}
+
+impl Deployment {
+    pub fn new(
+        room: Board,
+        (white_zone, white_army): (Zone, Vec<impl Into<Recruit>>),
+        (black_zone, black_army): (Zone, Vec<impl Into<Recruit>>),
+    ) -> Result<Self, DeployError> {
+        let mut deployment = Self {
+            board: room,
+            white: Side {
+                zone: white_zone,
+                army: Vec::new(),
+            },
+            black: Side {
+                zone: black_zone,
+                army: Vec::new(),
+            },
+        };
+        let white_army = white_army.into_iter().map(Into::into).collect();
+        let black_army = black_army.into_iter().map(Into::into).collect();
+        for (color, army) in [(Color::White, white_army), (Color::Black, black_army)] {
+            let army: Vec<Recruit> = army;
+            let count = army
+                .iter()
+                .filter(|recruit| recruit.piece == Piece::King)
+                .count();
+            if count != 1 {
+                return Err(DeployError::KingCount { color, count });
+            }
+            let forward = deployment.side(color).zone.forward;
+            deployment.side_mut(color).army = army
+                .into_iter()
+                .map(|recruit| Recruit {
+                    piece: deployed(recruit.piece, forward),
+                    ..recruit
+                })
+                .collect();
+        }
+        Ok(deployment)
+    }
+
+    fn side(&self, color: Color) -> &Side {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    fn side_mut(&mut self, color: Color) -> &mut Side {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn zone(&self, color: Color) -> &Zone {
+        &self.side(color).zone
+    }
+
+    pub fn army(&self, color: Color) -> &[Recruit] {
+        &self.side(color).army
+    }
+
+    fn find(&self, color: Color, piece: Piece) -> Result<usize, DeployError> {
+        // the first piece of the army of this kind, whatever unit it is
+        let piece = deployed(piece, self.zone(color).forward);
+        self.army(color)
+            .iter()
+            .position(|recruit| recruit.piece == piece)
+            .ok_or(DeployError::NotInArmy(piece))
+    }
+
+    fn find_unit(&self, color: Color, unit: UnitId) -> Result<usize, DeployError> {
+        self.army(color)
+            .iter()
+            .position(|recruit| recruit.unit == Some(unit))
+            .ok_or(DeployError::UnitNotInArmy(unit))
+    }
+
+    pub fn can_place(&self, color: Color, piece: Piece, pos: Pos) -> Result<(), DeployError> {
+        let i = self.find(color, piece)?;
+        check_square(
+            &self.board,
+            self.zone(color),
+            self.army(color)[i].piece,
+            pos,
+        )
+    }
+
+    pub fn can_place_unit(&self, color: Color, unit: UnitId, pos: Pos) -> Result<(), DeployError> {
+        let i = self.find_unit(color, unit)?;
+        check_square(
+            &self.board,
+            self.zone(color),
+            self.army(color)[i].piece,
+            pos,
+        )
+    }
+
+    fn place_at(&mut self, color: Color, i: usize, pos: Pos) -> Result<(), DeployError> {
+        let recruit = self.army(color)[i];
+        check_square(&self.board, self.zone(color), recruit.piece, pos)?;
+        self.side_mut(color).army.remove(i);
+        self.board.set(pos, Square::Piece(color, recruit.piece));
+        self.board.set_unit(pos, recruit.unit);
+        Ok(())
+    }
+
+    pub fn place(&mut self, color: Color, piece: Piece, pos: Pos) -> Result<(), DeployError> {
+        let i = self.find(color, piece)?;
+        self.place_at(color, i, pos)
+    }
+
+    pub fn place_unit(&mut self, color: Color, unit: UnitId, pos: Pos) -> Result<(), DeployError> {
+        // place this unit rather than any piece of its kind
+        let i = self.find_unit(color, unit)?;
+        self.place_at(color, i, pos)
+    }
+
+    pub fn remove(&mut self, color: Color, pos: Pos) -> Result<Piece, DeployError> {
+        // take back a placed piece, it goes back to the army
+        match self.board.get(pos) {
+            Some(Square::Piece(p_color, piece))
+                if *p_color == color && self.zone(color).contains(pos) =>
+            {
+                let recruit = Recruit {
+                    piece: *piece,
+                    unit: self.board.unit(pos),
+                };
+                self.board.set(pos, Square::Empty);
+                self.side_mut(color).army.push(recruit);
+                Ok(recruit.piece)
+            }
+            _ => Err(DeployError::NothingToRemove(pos)),
+        }
+    }
+
+    pub fn place_all(&mut self, color: Color, setup: &[(Pos, Recruit)]) -> Result<(), DeployError> {
+        // units go exactly where the setup puts them
+        for (pos, recruit) in setup {
+            match recruit.unit {
+                Some(unit) => self.place_unit(color, unit, *pos)?,
+                None => self.place(color, recruit.piece, *pos)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn auto_deploy(&mut self, color: Color, rng: &mut impl Rng) -> Result<(), DeployError> {
+        // see ai::deploy for a setup that is searched rather than guessed
+        let setup = simple_setup(&self.board, self.zone(color), color, self.army(color), rng)?;
+        self.place_all(color, &setup)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.white.army.is_empty() && self.black.army.is_empty()
+    }
+
+    pub fn start(self, player: Color) -> Result<Game, DeployError> {
+        // the match begins once every piece is on the board
+        for color in [Color::White, Color::Black] {
+            let count = self.army(color).len();
+            if count > 0 {
+                return Err(DeployError::Unplaced { color, count });
+            }
+        }
+        Ok(Game::new(self.board, player))
+    }
+}
+
+pub fn check_square(board: &Board, zone: &Zone, piece: Piece, pos: Pos) -> Result<(), DeployError> {
+    // whether piece can be deployed on pos, whatever is left to place
+    if !zone.contains(pos) {
+        return Err(DeployError::OutsideZone(pos));
+    }
+    if board.get(pos) != Some(&Square::Empty) {
+        return Err(DeployError::Occupied(pos));
+    }
+    // lava would destroy the piece on the spot
+    let terrain = board.terrain(pos);
+    if terrain == Terrain::Lava || !piece.can_enter(terrain) {
+        return Err(DeployError::Terrain(pos));
+    }
+    Ok(())
+}
+
+pub fn simple_setup(
+    room: &Board,
+    zone: &Zone,
+    color: Color,
+    army: &[Recruit],
+    rng: &mut impl Rng,
+) -> Result<Vec<(Pos, Recruit)>, DeployError> {
+    // place the army like a chess player would: the king at the back,
+    // the pawns in front, and the other pieces behind them, the most valuable first
+    let mut board = room.clone();
+    let mut army: Vec<Recruit> = army
+        .iter()
+        .map(|recruit| Recruit {
+            piece: deployed(recruit.piece, zone.forward),
+            ..*recruit
+        })
+        .collect();
+    army.sort_by_key(|recruit| match recruit.piece {
+        Piece::King => 0,
+        Piece::Pawn { .. } => 1,
+        Piece::Queen => 2,
+        Piece::Rook => 3,
+        Piece::Bishop => 4,
+        Piece::Knight => 5,
+    });
+    let mut setup = Vec::new();
+    for recruit in army {
+        let piece = recruit.piece;
+        let mut squares: Vec<Pos> = zone
+            .squares
+            .iter()
+            .copied()
+            .filter(|pos| check_square(&board, zone, piece, *pos).is_ok())
+            .collect();
+        // shuffled first so that squares as far forward are picked at random
+        squares.shuffle(rng);
+        let pos = match piece {
+            Piece::Pawn { .. } => squares.iter().max_by_key(|pos| zone.depth(**pos)),
+            _ => squares.iter().min_by_key(|pos| zone.depth(**pos)),
+        }
+        .copied()
+        .ok_or(DeployError::ZoneFull(piece))?;
+        board.set(pos, Square::Piece(color, piece));
+        setup.push((pos, recruit));
+    }
+    Ok(setup)
+}
+
+pub fn deployed(piece: Piece, forward: Pos) -> Piece {
+    // a deployed pawn faces the enemy and hasn't moved yet
+    match piece {
+        Piece::Pawn { .. } => Piece::Pawn {
+            orientation: forward,
+            status: PawnStatus::CanLeap,
+        },
+        _ => piece,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, Square, Terrain},
+        deploy::{standard_army, DeployError, Deployment, Recruit, Zone},
+        game::standard_board,
+        piece::{Color, PawnStatus, Piece},
+        pos::Pos,
+        unit::UnitId,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn deployment(room: Board, depth: usize) -> Deployment {
+        let white = (Zone::rows(&room, Color::White, depth), standard_army());
+        let black = (Zone::rows(&room, Color::Black, depth), standard_army());
+        Deployment::new(room, white, black).unwrap()
+    }
+
+    #[test]
+    fn standard() {
+        // placing the armies like in chess gives the standard board
+        let mut deployment = deployment(Board::new(8, 8), 2);
+        let back = standard_army();
+        for x in 0..8 {
+            let pawn = back[8 + x];
+            deployment
+                .place(Color::Black, back[x], Pos(x as i32, 0))
+                .unwrap();
+            deployment
+                .place(Color::Black, pawn, Pos(x as i32, 1))
+                .unwrap();
+            deployment
+                .place(Color::White, back[x], Pos(x as i32, 7))
+                .unwrap();
+            deployment
+                .place(Color::White, pawn, Pos(x as i32, 6))
+                .unwrap();
+        }
+        assert!(deployment.is_done());
+        let game = deployment.start(Color::White).unwrap();
+        assert!(game.board == standard_board());
+    }
+
+    #[test]
+    fn validation() {
+        let mut room = Board::from_layout(
+            "
+            ....
+            ....
+            ~!#.
+            ",
+        );
+        room.set_terrain(Pos(3, 2), Terrain::Ice);
+        let white = (
+            Zone::rows(&room, Color::White, 1),
+            vec![Piece::King, Piece::Rook],
+        );
+        let black = (Zone::rows(&room, Color::Black, 1), vec![Piece::King]);
+        let two_kings = (Zone::rows(&room, Color::Black, 1), vec![Piece::King; 2]);
+        assert!(
+            Deployment::new(room.clone(), white.clone(), two_kings).err()
+                == Some(DeployError::KingCount {
+                    color: Color::Black,
+                    count: 2
+                })
+        );
+        let mut deployment = Deployment::new(room, white, black).unwrap();
+        // the wall is not part of the zone
+        assert!(deployment.zone(Color::White).squares == vec![Pos(0, 2), Pos(1, 2), Pos(3, 2)]);
+        assert!(
+            deployment.place(Color::White, Piece::Rook, Pos(0, 1))
+                == Err(DeployError::OutsideZone(Pos(0, 1)))
+        );
+        assert!(
+            deployment.place(Color::White, Piece::Rook, Pos(0, 2))
+                == Err(DeployError::Terrain(Pos(0, 2)))
+        );
+        assert!(
+            deployment.place(Color::White, Piece::King, Pos(1, 2))
+                == Err(DeployError::Terrain(Pos(1, 2)))
+        );
+        assert!(
+            deployment.place(Color::White, Piece::Queen, Pos(3, 2))
+                == Err(DeployError::NotInArmy(Piece::Queen))
+        );
+        // the king can be carried through water
+        deployment
+            .place(Color::White, Piece::King, Pos(0, 2))
+            .unwrap();
+        deployment
+            .place(Color::White, Piece::Rook, Pos(3, 2))
+            .unwrap();
+        assert!(
+            deployment
+                .place(Color::Black, Piece::King, Pos(2, 0))
+                .is_ok()
+                && deployment.army(Color::Black).is_empty()
+        );
+        // taking a piece back returns it to the army
+        assert!(deployment.remove(Color::White, Pos(3, 2)) == Ok(Piece::Rook));
+        assert!(
+            deployment.remove(Color::White, Pos(2, 0))
+                == Err(DeployError::NothingToRemove(Pos(2, 0)))
+        );
+        assert!(
+            deployment.clone().start(Color::White).err()
+                == Some(DeployError::Unplaced {
+                    color: Color::White,
+                    count: 1
+                })
+        );
+        assert!(
+            deployment
+                .place(Color::White, Piece::Rook, Pos(3, 2))
+                .is_ok()
+                && deployment.is_done()
+        );
+        assert!(deployment.start(Color::White).is_ok());
+    }
+
+    #[test]
+    fn units() {
+        let room = Board::new(4, 4);
+        let knight = |id| Recruit {
+            piece: Piece::Knight,
+            unit: Some(UnitId(id)),
+        };
+        let white = (
+            Zone::rows(&room, Color::White, 1),
+            vec![Piece::King.into(), knight(1), knight(2)],
+        );
+        let black = (Zone::rows(&room, Color::Black, 1), vec![Piece::King]);
+        let mut deployment = Deployment::new(room, white, black).unwrap();
+        // the second knight goes first, the board knows which one it is
+        deployment
+            .place_unit(Color::White, UnitId(2), Pos(0, 3))
+            .unwrap();
+        assert!(deployment.board().unit(Pos(0, 3)) == Some(UnitId(2)));
+        assert!(
+            deployment.place_unit(Color::White, UnitId(2), Pos(1, 3))
+                == Err(DeployError::UnitNotInArmy(UnitId(2)))
+        );
+        // taken back, it is still the same unit
+        deployment.remove(Color::White, Pos(0, 3)).unwrap();
+        assert!(deployment.board().unit(Pos(0, 3)).is_none());
+        deployment
+            .place_all(
+                Color::White,
+                &[(Pos(3, 3), knight(2)), (Pos(2, 3), knight(1))],
+            )
+            .unwrap();
+        assert!(deployment.board().unit(Pos(3, 3)) == Some(UnitId(2)));
+        assert!(deployment.board().unit(Pos(2, 3)) == Some(UnitId(1)));
+        assert!(deployment.army(Color::White) == [Piece::King.into()]);
+    }
+
+    #[test]
+    fn auto_deploy() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let mut room = Board::from_layout(
+                "
+                ..~~..*.
+                ........
+                .##..##.
+                ........
+                ........
+                .##..##.
+                ..!!....
+                ~~..**..
+                ",
+            );
+            room.set_terrain(Pos(7, 0), Terrain::Fortification);
+            let mut deployment = deployment(room, 3);
+            deployment.auto_deploy(Color::White, &mut rng).unwrap();
+            deployment.auto_deploy(Color::Black, &mut rng).unwrap();
+            let board = deployment.board().clone();
+            for color in [Color::White, Color::Black] {
+                let zone = deployment.zone(color);
+                let placed: Vec<(Pos, Piece)> = zone
+                    .squares
+                    .iter()
+                    .filter_map(|pos| match board.get(*pos) {
+                        Some(Square::Piece(p_color, piece)) if *p_color == color => {
+                            Some((*pos, *piece))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                assert!(placed.len() == 16);
+                // pawns face the enemy, in front of the king
+                let king = board.king_pos(color).unwrap();
+                for (pos, piece) in placed {
+                    if let Piece::Pawn {
+                        orientation,
+                        status,
+                    } = piece
+                    {
+                        assert!(orientation == zone.forward && status == PawnStatus::CanLeap);
+                        assert!(zone.depth(pos) > zone.depth(king));
+                    }
+                    assert!(board.terrain(pos) != Terrain::Lava);
+                }
+            }
+            let game = deployment.start(Color::White).unwrap();
+            assert!(!game.legal_moves().is_empty());
+        }
+        // an army that doesn't fit in its zone
+        let mut deployment = deployment(Board::new(4, 4), 1);
+        assert!(matches!(
+            deployment.auto_deploy(Color::White, &mut rng),
+            Err(DeployError::ZoneFull(_))
+        ));
+    }
+}
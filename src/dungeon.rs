@@ -0,0 +1,373 @@
+// A run through the dungeon: floors of encounters linked to the next floor, from the entrance to the boss
+use crate::board::{splitmix64, Board};
+use crate::deploy::{check_square, DeployError, Deployment, Recruit, Zone};
+use crate::eval::{Evaluator, MaterialEvaluator};
+use crate::piece::{PawnStatus, Piece};
+use crate::pos::Pos;
+use crate::room::{self, Biome, Room};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Encounter {
+    Fight,
+    Elite,
+    Boss,
+    Shop,
+    Rest,
+    Event,
+}
+
+impl Encounter {
+    pub fn is_fight(self) -> bool {
+        matches!(self, Encounter::Fight | Encounter::Elite | Encounter::Boss)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Node {
+    pub encounter: Encounter,
+    // the nodes of the next floor that can be reached from this one
+    pub next: Vec<usize>,
+}
+
+pub struct Fight {
+    pub room: Board,
+    // the player is white and deploys at the bottom of the room
+    pub player_zone: Zone,
+    pub enemy_zone: Zone,
+    pub enemy: Vec<Piece>,
+}
+
+impl Fight {
+    pub fn deployment(
+        &self,
+        player_army: Vec<impl Into<Recruit>>,
+    ) -> Result<Deployment, DeployError> {
+        Deployment::new(
+            self.room.clone(),
+            (self.player_zone.clone(), player_army),
+            (self.enemy_zone.clone(), self.enemy.clone()),
+        )
+    }
+}
+
+fn encounter(floor: usize, floors: usize, rng: &mut impl Rng) -> Encounter {
+    // the first floor is an easy start and the player can rest before the boss,
+    // unless the first floor is the one before the boss
+    if floor == floors - 1 {
+        return Encounter::Boss;
+    }
+    if floor == 0 {
+        return Encounter::Fight;
+    }
+    if floor == floors - 2 {
+        return Encounter::Rest;
+    }
+    match rng.gen_range(0..100) {
+        0..=44 => Encounter::Fight,
+        // no elite before the player had a chance to grow
+        45..=59 if floor >= 3 => Encounter::Elite,
+        45..=59 => Encounter::Fight,
+        60..=69 => Encounter::Shop,
+        70..=79 => Encounter::Rest,
+        _ => Encounter::Event,
+    }
+}
+
+fn closest(i: usize, len: usize, other_len: usize) -> usize {
+    // the node of a floor of other_len nodes that is in front of node i of a floor of len nodes
+    if len <= 1 {
+        return other_len / 2;
+    }
+    (i * (other_len - 1) + (len - 1) / 2) / (len - 1)
+}
+
+fn enemy_army(
+    depth: usize,
+    encounter: Encounter,
+    capacity: usize,
+    rng: &mut impl Rng,
+) -> Vec<Piece> {
+    // a king and as many pieces as the budget of the floor can buy
+    let mut budget = 4. + 3. * depth as f32;
+    budget *= match encounter {
+        Encounter::Elite => 1.5,
+        Encounter::Boss => 2.,
+        _ => 1.,
+    };
+    let mut army = vec![Piece::King];
+    if encounter == Encounter::Boss && budget >= 9. {
+        army.push(Piece::Queen);
+        budget -= 9.;
+    }
+    let pieces = [
+        Piece::Pawn {
+            orientation: Pos(0, 1),
+            status: PawnStatus::CanLeap,
+        },
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ];
+    while army.len() < capacity {
+        let affordable: Vec<Piece> = pieces
+            .iter()
+            .copied()
+            .filter(|piece| MaterialEvaluator.piece_value(*piece) <= budget)
+            .collect();
+        let Some(piece) = affordable.choose(rng) else {
+            break;
+        };
+        budget -= MaterialEvaluator.piece_value(*piece);
+        army.push(*piece);
+    }
+    army
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Dungeon {
+    pub seed: u64,
+    floors: Vec<Vec<Node>>,
+    // the floor and the node the player is on, None before entering the dungeon
+    position: Option<(usize, usize)>,
+}
+
+impl Dungeon {
+    pub fn generate(seed: u64, floors: usize) -> Self {
+        // there is at least a floor to fight on before the boss
+        let floors = floors.max(2);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let widths: Vec<usize> = (0..floors)
+            .map(|floor| match floor {
+                0 => 3,
+                _ if floor == floors - 1 => 1,
+                _ => rng.gen_range(2..=4),
+            })
+            .collect();
+        let mut map: Vec<Vec<Node>> = widths
+            .iter()
+            .enumerate()
+            .map(|(floor, width)| {
+                (0..*width)
+                    .map(|_| Node {
+                        encounter: encounter(floor, floors, &mut rng),
+                        next: Vec::new(),
+                    })
+                    .collect()
+            })
+            .collect();
+        for floor in 0..floors - 1 {
+            let (len, next_len) = (widths[floor], widths[floor + 1]);
+            for (i, node) in map[floor].iter_mut().enumerate() {
+                let j = closest(i, len, next_len);
+                node.next.push(j);
+                // sometimes a path branches to a neighbour
+                if rng.gen_bool(0.4) {
+                    let k = if rng.gen_bool(0.5) {
+                        j.checked_sub(1)
+                    } else {
+                        Some(j + 1)
+                    };
+                    if let Some(k) = k.filter(|k| *k < next_len) {
+                        node.next.push(k);
+                    }
+                }
+            }
+            // every node of the next floor can be reached
+            for j in 0..next_len {
+                if !map[floor].iter().any(|node| node.next.contains(&j)) {
+                    map[floor][closest(j, next_len, len)].next.push(j);
+                }
+            }
+            for node in map[floor].iter_mut() {
+                node.next.sort_unstable();
+                node.next.dedup();
+            }
+        }
+        Self {
+            seed,
+            floors: map,
+            position: None,
+        }
+    }
+
+    pub fn floors(&self) -> &[Vec<Node>] {
+        &self.floors
+    }
+
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.position
+    }
+
+    pub fn current(&self) -> Option<&Node> {
+        self.position.map(|(floor, i)| &self.floors[floor][i])
+    }
+
+    pub fn choices(&self) -> Vec<usize> {
+        // the nodes of the next floor the player can go to
+        match self.current() {
+            Some(node) => node.next.clone(),
+            None => (0..self.floors[0].len()).collect(),
+        }
+    }
+
+    pub fn advance(&mut self, i: usize) -> Option<Encounter> {
+        // go to node i of the next floor, None if it can't be reached from here
+        if !self.choices().contains(&i) {
+            return None;
+        }
+        let floor = self.position.map_or(0, |(floor, _)| floor + 1);
+        self.position = Some((floor, i));
+        Some(self.floors[floor][i].encounter)
+    }
+
+    pub fn is_over(&self) -> bool {
+        // the player made it to the boss
+        self.position
+            .is_some_and(|(floor, _)| floor == self.floors.len() - 1)
+    }
+
+    pub fn biome(&self, floor: usize) -> Biome {
+        // the dungeon changes every few floors
+        Biome::ALL[(floor / 3) % Biome::ALL.len()]
+    }
+
+    pub fn fight(&self, army: usize) -> Option<Fight> {
+        // the fight of the current node for a player army of that many pieces,
+        // the same every time for a given seed and army
+        let (floor, i) = self.position?;
+        let encounter = self.floors[floor][i].encounter;
+        if !encounter.is_fight() {
+            return None;
+        }
+        let mut rng =
+            StdRng::seed_from_u64(splitmix64(self.seed ^ ((floor as u64) << 32 | i as u64)));
+        let Room {
+            board: room,
+            white_zone: player_zone,
+            black_zone: enemy_zone,
+        } = room::generate(rng.gen(), floor, self.biome(floor), army);
+        // as many pieces as there are squares where any piece can stand
+        let capacity = enemy_zone
+            .squares
+            .iter()
+            .filter(|pos| check_square(&room, &enemy_zone, Piece::Rook, **pos).is_ok())
+            .count();
+        let enemy = enemy_army(floor, encounter, capacity, &mut rng);
+        Some(Fight {
+            room,
+            player_zone,
+            enemy_zone,
+            enemy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        deploy::standard_army,
+        dungeon::{enemy_army, Dungeon, Encounter},
+        eval::{Evaluator, MaterialEvaluator},
+        piece::{Color, Piece},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn map() {
+        for seed in 0..20 {
+            let dungeon = Dungeon::generate(seed, 10);
+            assert!(dungeon == Dungeon::generate(seed, 10));
+            let floors = dungeon.floors();
+            assert!(floors.len() == 10 && floors[9].len() == 1);
+            assert!(floors[9][0].encounter == Encounter::Boss);
+            assert!(floors[..3]
+                .iter()
+                .flatten()
+                .all(|node| node.encounter != Encounter::Elite));
+            // every node leads somewhere and can be reached
+            for floor in 0..9 {
+                assert!(floors[floor].iter().all(|node| !node.next.is_empty()));
+                for j in 0..floors[floor + 1].len() {
+                    assert!(floors[floor].iter().any(|node| node.next.contains(&j)));
+                }
+            }
+        }
+        assert!(Dungeon::generate(0, 10) != Dungeon::generate(1, 10));
+    }
+
+    #[test]
+    fn smallest() {
+        // there is always a fight before the boss
+        for count in 0..=3 {
+            let dungeon = Dungeon::generate(0, count);
+            let floors = dungeon.floors();
+            assert!(floors.len() == count.max(2));
+            assert!(floors[0]
+                .iter()
+                .all(|node| node.encounter == Encounter::Fight));
+            assert!(floors[floors.len() - 1][0].encounter == Encounter::Boss);
+        }
+        assert!(Dungeon::generate(0, 3).floors()[1]
+            .iter()
+            .all(|node| node.encounter == Encounter::Rest));
+    }
+
+    #[test]
+    fn run() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut dungeon = Dungeon::generate(3, 8);
+        let army = standard_army();
+        assert!(dungeon.position().is_none() && dungeon.fight(army.len()).is_none());
+        assert!(dungeon.advance(3).is_none());
+        while !dungeon.is_over() {
+            let choices = dungeon.choices();
+            let floor = dungeon.position().map_or(0, |(floor, _)| floor + 1);
+            let encounter = dungeon.advance(choices[choices.len() - 1]).unwrap();
+            assert!(dungeon.position() == Some((floor, choices[choices.len() - 1])));
+            let Some(fight) = dungeon.fight(army.len()) else {
+                assert!(!encounter.is_fight());
+                continue;
+            };
+            assert!(encounter.is_fight());
+            // the same fight every time
+            assert!(dungeon.fight(army.len()).unwrap().enemy == fight.enemy);
+            let mut deployment = fight.deployment(army.clone()).unwrap();
+            deployment.auto_deploy(Color::White, &mut rng).unwrap();
+            deployment.auto_deploy(Color::Black, &mut rng).unwrap();
+            assert!(deployment.start(Color::White).is_ok());
+        }
+        assert!(dungeon.current().unwrap().encounter == Encounter::Boss);
+        assert!(dungeon.choices().is_empty());
+    }
+
+    #[test]
+    fn scaling() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let material = |army: &[Piece]| -> f32 {
+            army.iter()
+                .filter(|piece| **piece != Piece::King)
+                .map(|piece| MaterialEvaluator.piece_value(*piece))
+                .sum()
+        };
+        for _ in 0..20 {
+            let shallow = enemy_army(1, Encounter::Fight, 16, &mut rng);
+            let deep = enemy_army(8, Encounter::Fight, 16, &mut rng);
+            let elite = enemy_army(8, Encounter::Elite, 16, &mut rng);
+            assert!(material(&shallow) < material(&deep));
+            assert!(material(&deep) <= 28. && material(&elite) > 28.);
+            assert!(
+                shallow
+                    .iter()
+                    .filter(|piece| **piece == Piece::King)
+                    .count()
+                    == 1
+            );
+            // an army never outgrows its zone
+            assert!(enemy_army(8, Encounter::Boss, 4, &mut rng).len() == 4);
+        }
+    }
+}
@@ -0,0 +1,501 @@
+// Evaluation functions for the AI, from the point of view of a player.
+//
+// Besides the built in material evaluator, evaluators can be described in a TOML file so that
+// game designers can tune enemy personalities without recompiling:
+//
+//   centrality = 1.0        # how much pieces like the center, for boards without a table
+//   mobility = 0.05         # per pseudo legal move
+//   king_safety = 0.1       # per friendly piece next to the king
+//   doubled_pawns = -0.3    # per pawn with another friendly pawn ahead of it
+//   isolated_pawns = -0.2   # per pawn without friendly pawns on the neighboring files
+//   passed_pawns = 0.5      # per pawn without enemy pawns ahead of it on its file or the neighboring ones
+//
+//   [pieces]
+//   knight = 3.2            # any of pawn, knight, bishop, rook, queen and king, the others keep their value
+//
+//   [[tables]]              # a piece-square table, for one piece and one board size
+//   piece = "knight"
+//   width = 3
+//   height = 2
+//   values = [[0.0, 0.1, 0.0], [0.1, 0.2, 0.1]]
+//
+// Tables are written from white's side, with the first row at the top of the board, and flipped for black.
+// Every field is optional, the default configuration gives the same scores as the material evaluator.
+use crate::board::{Board, Square};
+use crate::piece::{Color, Piece};
+use crate::pos::{Pos, LOS};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::Arc;
+
+pub trait Evaluator: Send + Sync {
+    fn piece_value(&self, piece: Piece) -> f32;
+
+    // the score of the board for player, the opposite of the score for the other player
+    fn evaluate(&self, board: &Board, player: Color) -> f32;
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for Box<E> {
+    fn piece_value(&self, piece: Piece) -> f32 {
+        (**self).piece_value(piece)
+    }
+
+    fn evaluate(&self, board: &Board, player: Color) -> f32 {
+        (**self).evaluate(board, player)
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for Arc<E> {
+    fn piece_value(&self, piece: Piece) -> f32 {
+        (**self).piece_value(piece)
+    }
+
+    fn evaluate(&self, board: &Board, player: Color) -> f32 {
+        (**self).evaluate(board, player)
+    }
+}
+
+fn piece_value(piece: Piece) -> f32 {
+    match piece {
+        Piece::Pawn {
+            orientation: _,
+            status: _,
+        } => 1.,
+        Piece::Knight => 3.,
+        Piece::Bishop => 3.5,
+        Piece::Rook => 5.,
+        Piece::Queen => 9.,
+        Piece::King => 1000.,
+    }
+}
+
+fn axis_value(x: i32, len: usize) -> f32 {
+    // score a single axis of a position, gives more value to center
+    if len <= 1 {
+        return 0.5;
+    }
+    0.5 - f32::abs(x as f32 / (len - 1) as f32 - 0.5)
+}
+
+fn pos_value(board: &Board, pos: Pos) -> f32 {
+    // score a position, gives more value to center
+    axis_value(pos.0, board.width) * axis_value(pos.1, board.height)
+}
+
+fn sign(color: Color, player: Color) -> f32 {
+    if color == player {
+        1.
+    } else {
+        -1.
+    }
+}
+
+// material and centrality
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn piece_value(&self, piece: Piece) -> f32 {
+        piece_value(piece)
+    }
+
+    fn evaluate(&self, board: &Board, player: Color) -> f32 {
+        board
+            .squares()
+            .iter()
+            .enumerate()
+            .map(|(i, square)| {
+                if let Square::Piece(color, piece) = square {
+                    (piece_value(*piece) + pos_value(board, board.pos(i))) * sign(*color, player)
+                } else {
+                    0.
+                }
+            })
+            .fold(0., |a, b| a + b)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceKind {
+    fn of(piece: Piece) -> Self {
+        match piece {
+            Piece::Pawn { .. } => PieceKind::Pawn,
+            Piece::Knight => PieceKind::Knight,
+            Piece::Bishop => PieceKind::Bishop,
+            Piece::Rook => PieceKind::Rook,
+            Piece::Queen => PieceKind::Queen,
+            Piece::King => PieceKind::King,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PieceSquareTable {
+    pub piece: PieceKind,
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<Vec<f32>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalConfig {
+    pub pieces: HashMap<PieceKind, f32>,
+    pub centrality: f32,
+    pub mobility: f32,
+    pub king_safety: f32,
+    pub doubled_pawns: f32,
+    pub isolated_pawns: f32,
+    pub passed_pawns: f32,
+    pub tables: Vec<PieceSquareTable>,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self {
+            pieces: HashMap::new(),
+            centrality: 1.,
+            mobility: 0.,
+            king_safety: 0.,
+            doubled_pawns: 0.,
+            isolated_pawns: 0.,
+            passed_pawns: 0.,
+            tables: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EvalError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    TableSize {
+        piece: PieceKind,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Io(err) => write!(f, "could not read the evaluation file: {}", err),
+            EvalError::Toml(err) => write!(f, "invalid evaluation file: {}", err),
+            EvalError::TableSize {
+                piece,
+                width,
+                height,
+            } => write!(
+                f,
+                "the {:?} table does not have {} rows of {} values",
+                piece, height, width
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+// an evaluator driven by an EvalConfig
+#[derive(Clone, Debug, Default)]
+pub struct ConfigEvaluator {
+    config: EvalConfig,
+    // the tables by piece and board size
+    tables: HashMap<(PieceKind, usize, usize), Vec<Vec<f32>>>,
+}
+
+impl ConfigEvaluator {
+    pub fn new(config: EvalConfig) -> Result<Self, EvalError> {
+        let mut tables = HashMap::new();
+        for table in &config.tables {
+            if table.values.len() != table.height
+                || table.values.iter().any(|row| row.len() != table.width)
+            {
+                return Err(EvalError::TableSize {
+                    piece: table.piece,
+                    width: table.width,
+                    height: table.height,
+                });
+            }
+            tables.insert(
+                (table.piece, table.width, table.height),
+                table.values.clone(),
+            );
+        }
+        Ok(Self { config, tables })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, EvalError> {
+        Self::new(toml::from_str(text).map_err(EvalError::Toml)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EvalError> {
+        Self::from_toml(&std::fs::read_to_string(path).map_err(EvalError::Io)?)
+    }
+
+    fn square_value(&self, board: &Board, pos: Pos, color: Color, piece: Piece) -> f32 {
+        match self
+            .tables
+            .get(&(PieceKind::of(piece), board.width, board.height))
+        {
+            Some(values) => {
+                let row = match color {
+                    Color::White => pos.1 as usize,
+                    Color::Black => board.height - 1 - pos.1 as usize,
+                };
+                values[row][pos.0 as usize]
+            }
+            None => self.config.centrality * pos_value(board, pos),
+        }
+    }
+
+    fn king_safety(&self, board: &Board, pos: Pos, color: Color) -> f32 {
+        LOS.iter()
+            .filter(|dir| matches!(board.get(pos + **dir), Some(Square::Piece(o_color, _)) if *o_color == color))
+            .count() as f32
+            * self.config.king_safety
+    }
+
+    fn pawn_structure(
+        &self,
+        pawns: &[(Pos, Color, Pos)],
+        pos: Pos,
+        color: Color,
+        orientation: Pos,
+    ) -> f32 {
+        // files and ranks are relative to the pawn's orientation, so that sideways pawns are judged like the others
+        let side = Pos(-orientation.1, orientation.0);
+        let file = |pos: Pos| pos.0 * side.0 + pos.1 * side.1;
+        let rank = |pos: Pos| pos.0 * orientation.0 + pos.1 * orientation.1;
+        let mut value = 0.;
+        let friends = pawns.iter().filter(|(other, o_color, o_orientation)| {
+            *other != pos && *o_color == color && *o_orientation == orientation
+        });
+        if friends
+            .clone()
+            .any(|(other, _, _)| file(*other) == file(pos) && rank(*other) > rank(pos))
+        {
+            value += self.config.doubled_pawns;
+        }
+        if !friends
+            .clone()
+            .any(|(other, _, _)| (file(*other) - file(pos)).abs() == 1)
+        {
+            value += self.config.isolated_pawns;
+        }
+        if !pawns.iter().any(|(other, o_color, _)| {
+            *o_color != color && (file(*other) - file(pos)).abs() <= 1 && rank(*other) > rank(pos)
+        }) {
+            value += self.config.passed_pawns;
+        }
+        value
+    }
+
+    fn color_score(&self, board: &Board, color: Color, pawns: &[(Pos, Color, Pos)]) -> f32 {
+        let mut score = 0.;
+        for (i, square) in board.squares().iter().enumerate() {
+            if let Square::Piece(p_color, piece) = *square {
+                if p_color != color {
+                    continue;
+                }
+                let pos = board.pos(i);
+                score += self.piece_value(piece) + self.square_value(board, pos, color, piece);
+                match piece {
+                    Piece::King if self.config.king_safety != 0. => {
+                        score += self.king_safety(board, pos, color)
+                    }
+                    Piece::Pawn { orientation, .. } => {
+                        score += self.pawn_structure(pawns, pos, color, orientation)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // generating the moves is costly, it's only done when mobility matters
+        if self.config.mobility != 0. {
+            score += self.config.mobility * board.moves(color, false).len() as f32;
+        }
+        score
+    }
+}
+
+impl Evaluator for ConfigEvaluator {
+    fn piece_value(&self, piece: Piece) -> f32 {
+        self.config
+            .pieces
+            .get(&PieceKind::of(piece))
+            .copied()
+            .unwrap_or_else(|| piece_value(piece))
+    }
+
+    fn evaluate(&self, board: &Board, player: Color) -> f32 {
+        let pawns: Vec<(Pos, Color, Pos)> = board
+            .squares()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, square)| match square {
+                Square::Piece(color, Piece::Pawn { orientation, .. }) => {
+                    Some((board.pos(i), *color, *orientation))
+                }
+                _ => None,
+            })
+            .collect();
+        self.color_score(board, player, &pawns) - self.color_score(board, player.next(), &pawns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{
+            tests::{random_board, random_dims},
+            Board, Square,
+        },
+        eval::{ConfigEvaluator, EvalError, Evaluator, MaterialEvaluator, PieceKind},
+        fen::fen2board,
+        piece::{Color, Piece},
+        pos::Pos,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn mirror(board: &Board) -> Board {
+        // flip the board vertically and swap the colors, which should not change the evaluation
+        let mut mirrored = Board::new(board.width, board.height);
+        for (i, square) in board.squares().iter().enumerate() {
+            let pos = board.pos(i);
+            let mirrored_pos = Pos(pos.0, board.height as i32 - 1 - pos.1);
+            mirrored.set(
+                mirrored_pos,
+                square.piece().map_or(*square, |(color, piece)| {
+                    let piece = match piece {
+                        Piece::Pawn {
+                            orientation,
+                            status,
+                        } => Piece::Pawn {
+                            orientation: Pos(orientation.0, -orientation.1),
+                            status,
+                        },
+                        _ => piece,
+                    };
+                    Square::Piece(color.next(), piece)
+                }),
+            );
+        }
+        mirrored
+    }
+
+    fn assert_symmetric(evaluator: &impl Evaluator, board: &Board) {
+        let white_score = evaluator.evaluate(board, Color::White);
+        assert!((white_score + evaluator.evaluate(board, Color::Black)).abs() < 1e-3);
+        assert!((white_score - evaluator.evaluate(&mirror(board), Color::Black)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn evaluation_symmetry() {
+        let config_evaluator = ConfigEvaluator::from_toml(
+            "mobility = 0.1\nking_safety = 0.2\ndoubled_pawns = -0.3\nisolated_pawns = -0.2\npassed_pawns = 0.5",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let board = random_board(&mut rng, width, height);
+            assert_symmetric(&MaterialEvaluator, &board);
+            assert_symmetric(&config_evaluator, &board);
+            // the default configuration is the material evaluator
+            assert!(
+                (ConfigEvaluator::default().evaluate(&board, Color::White)
+                    - MaterialEvaluator.evaluate(&board, Color::White))
+                .abs()
+                    < 1e-3
+            );
+        }
+    }
+
+    #[test]
+    fn config() {
+        let evaluator = ConfigEvaluator::from_toml(
+            r#"
+            centrality = 0.0
+            [pieces]
+            knight = 4.0
+            [[tables]]
+            piece = "king"
+            width = 3
+            height = 2
+            values = [[0.0, 0.0, 0.0], [0.5, 0.0, 0.0]]
+            "#,
+        )
+        .unwrap();
+        assert!(evaluator.piece_value(Piece::Knight) == 4.);
+        assert!(evaluator.piece_value(Piece::Rook) == 5.);
+        // the white king is on the bottom left square of the table, the black one on its top right square
+        let mut board = Board::new(3, 2);
+        board.set(Pos(0, 1), Square::Piece(Color::White, Piece::King));
+        board.set(Pos(2, 0), Square::Piece(Color::Black, Piece::King));
+        assert!(evaluator.evaluate(&board, Color::White) == 0.5);
+        // black's table is flipped vertically only
+        board.set(Pos(2, 0), Square::Empty);
+        board.set(Pos(0, 0), Square::Piece(Color::Black, Piece::King));
+        assert!(evaluator.evaluate(&board, Color::White) == 0.);
+        let err = ConfigEvaluator::from_toml(
+            "[[tables]]\npiece = \"pawn\"\nwidth = 2\nheight = 1\nvalues = [[1.0]]",
+        );
+        assert!(matches!(
+            err,
+            Err(EvalError::TableSize {
+                piece: PieceKind::Pawn,
+                ..
+            })
+        ));
+        assert!(matches!(
+            ConfigEvaluator::from_toml("speed = 2.0"),
+            Err(EvalError::Toml(_))
+        ));
+        assert!(matches!(
+            ConfigEvaluator::load("no/such/file.toml"),
+            Err(EvalError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn pawn_structure() {
+        let evaluator = ConfigEvaluator::from_toml(
+            "centrality = 0.0\ndoubled_pawns = -0.3\nisolated_pawns = -0.2\npassed_pawns = 0.5",
+        )
+        .unwrap();
+        let material = |fen: &str| {
+            let (board, color) = fen2board(fen).unwrap();
+            ConfigEvaluator::from_toml("centrality = 0.0")
+                .unwrap()
+                .evaluate(&board, color)
+        };
+        let structure = |fen: &str| {
+            let (board, color) = fen2board(fen).unwrap();
+            evaluator.evaluate(&board, color) - material(fen)
+        };
+        // two isolated passed pawns, one doubled behind the other
+        assert!(
+            (structure("4k3/8/8/4P3/4P3/8/8/4K3 w") - (-0.3 - 0.2 * 2. + 0.5 * 2.)).abs() < 1e-3
+        );
+        // isolated pawns blocking each other on both sides
+        assert!(structure("4k3/3p4/8/8/3P4/8/8/4K3 w").abs() < 1e-3);
+        // sideways pawns have files along their orientation, the black pawn isn't in front of them
+        assert!(
+            (structure("4k3/8/3p4/8/P[e]7/P[e]7/8/4K3 w") - (0.5 * 2. - (0.5 - 0.2))).abs() < 1e-3
+        );
+    }
+}
@@ -0,0 +1,756 @@
+// A superset of FEN that can describe any DungeonChess position:
+//
+//   <placement> <side> [<castling> <en passant> <halfmove> <fullmove> [<terrain>]]
+//
+// - placement: ranks from top to bottom separated by '/', with the usual piece letters
+//   (uppercase for white), numbers (possibly several digits) for runs of empty squares and '#' for walls.
+//   Pawns can be followed by a modifier in brackets giving their orientation as a compass direction
+//   (n, ne, e, se, s, sw, w, nw) and their status ('+' can leap, '!' just leaped, '-' cannot leap),
+//   e.g. P[e+]. Both are optional, white pawns face north and black pawns south by default,
+//   and pawns can leap by default only if they stand next to the edge behind them.
+// - castling: ignored since there is no castling in DungeonChess.
+// - terrain: ranks like the placement, with numbers for runs of floor, 'i' ice, 'l' lava, 'w' water,
+//   'f' fortification and 't' followed by the linked square in brackets for teleporters, e.g. t[c3].
+//
+// Standard FEN strings are valid and describe the same position.
+use crate::board::{Board, Square, Terrain, MAX_HEIGHT, MAX_WIDTH};
+use crate::game::Game;
+use crate::pgn::{pgn2pos, pos2pgn};
+use crate::piece::{Color, PawnStatus, Piece};
+use crate::pos::{Pos, LOS};
+use std::fmt::Display;
+
+pub const STANDARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields(usize),
+    UnevenRank {
+        rank: usize,
+        width: usize,
+        expected: usize,
+    },
+    InvalidChar {
+        rank: usize,
+        c: char,
+    },
+    InvalidPawnModifier(String),
+    InvalidSide(String),
+    InvalidSquare(String),
+    InvalidNumber(String),
+    NoEnPassantPawn(String),
+    TerrainSize,
+    TooLarge {
+        width: usize,
+    },
+    TooTall {
+        height: usize,
+    },
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing the {} field", field),
+            FenError::TooManyFields(count) => {
+                write!(f, "expected at most 7 fields, found {}", count)
+            }
+            FenError::UnevenRank {
+                rank,
+                width,
+                expected,
+            } => write!(
+                f,
+                "rank {} is {} squares wide but the first one is {}",
+                rank, width, expected
+            ),
+            FenError::InvalidChar { rank, c } => {
+                write!(f, "unexpected character '{}' in rank {}", c, rank)
+            }
+            FenError::InvalidPawnModifier(modifier) => {
+                write!(f, "invalid pawn modifier [{}]", modifier)
+            }
+            FenError::InvalidSide(side) => {
+                write!(f, "side to move must be 'w' or 'b', found '{}'", side)
+            }
+            FenError::InvalidSquare(square) => {
+                write!(f, "'{}' is not a square of the board", square)
+            }
+            FenError::InvalidNumber(number) => write!(f, "'{}' is not a valid number", number),
+            FenError::NoEnPassantPawn(square) => {
+                write!(f, "no pawn can have just leaped over {}", square)
+            }
+            FenError::TerrainSize => write!(f, "the terrain does not have the board's dimensions"),
+            FenError::TooLarge { width } => write!(
+                f,
+                "the board is {} squares wide, at most 26 files are supported",
+                width
+            ),
+            FenError::TooTall { height } => write!(
+                f,
+                "the board has {} ranks, at most {} are supported",
+                height, MAX_HEIGHT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+const DIRS: [(&str, Pos); 8] = [
+    ("n", Pos(0, -1)),
+    ("ne", Pos(1, -1)),
+    ("e", Pos(1, 0)),
+    ("se", Pos(1, 1)),
+    ("s", Pos(0, 1)),
+    ("sw", Pos(-1, 1)),
+    ("w", Pos(-1, 0)),
+    ("nw", Pos(-1, -1)),
+];
+
+fn default_orientation(color: Color) -> Pos {
+    match color {
+        Color::White => Pos(0, -1),
+        Color::Black => Pos(0, 1),
+    }
+}
+
+fn default_status(board: &Board, pos: Pos, orientation: Pos) -> PawnStatus {
+    // pawns can leap if they're on the second rank from their edge of the board
+    let back = orientation * -1;
+    if !board.is_edge(pos, back) && board.is_edge(pos + back, back) {
+        PawnStatus::CanLeap
+    } else {
+        PawnStatus::CannotLeap
+    }
+}
+
+fn piece2fen(color: Color, piece: Piece) -> char {
+    let c = match piece {
+        Piece::Pawn { .. } => 'p',
+        Piece::Knight => 'n',
+        Piece::Bishop => 'b',
+        Piece::Rook => 'r',
+        Piece::Queen => 'q',
+        Piece::King => 'k',
+    };
+    match color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+fn fen2piece(c: char) -> Option<(Color, Piece)> {
+    let piece = match c.to_ascii_lowercase() {
+        // the pawn's orientation and status are filled in once the whole board is known
+        'p' => Piece::Pawn {
+            orientation: Pos(0, 0),
+            status: PawnStatus::CannotLeap,
+        },
+        'n' => Piece::Knight,
+        'b' => Piece::Bishop,
+        'r' => Piece::Rook,
+        'q' => Piece::Queen,
+        'k' => Piece::King,
+        _ => return None,
+    };
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((color, piece))
+}
+
+fn status2fen(status: PawnStatus) -> char {
+    match status {
+        PawnStatus::CanLeap => '+',
+        PawnStatus::JustLeaped => '!',
+        PawnStatus::CannotLeap => '-',
+    }
+}
+
+fn write_runs(ranks: Vec<Vec<Option<String>>>) -> String {
+    // join ranks of tokens, with numbers for the runs of None
+    let mut res = Vec::new();
+    for rank in ranks {
+        let mut rank_str = String::new();
+        let mut run = 0;
+        for token in rank {
+            if let Some(token) = token {
+                if run > 0 {
+                    rank_str += &run.to_string();
+                    run = 0;
+                }
+                rank_str += &token;
+            } else {
+                run += 1;
+            }
+        }
+        if run > 0 {
+            rank_str += &run.to_string();
+        }
+        res.push(rank_str);
+    }
+    res.join("/")
+}
+
+fn pawn_modifier(board: &Board, pos: Pos, color: Color, en_passant: Option<Pos>) -> String {
+    let (orientation, status) = match board.get(pos) {
+        Some(Square::Piece(
+            _,
+            Piece::Pawn {
+                orientation,
+                status,
+            },
+        )) => (*orientation, *status),
+        _ => return String::new(),
+    };
+    let mut modifier = String::new();
+    if orientation != default_orientation(color) {
+        // only the 8 directions can be written, a longer orientation is written as its direction
+        let dir = Pos(orientation.0.signum(), orientation.1.signum());
+        if let Some((name, _)) = DIRS.iter().find(|(_, other)| *other == dir) {
+            modifier += name;
+        }
+    }
+    let implied_status = if en_passant == Some(pos) {
+        PawnStatus::JustLeaped
+    } else {
+        default_status(board, pos, orientation)
+    };
+    if status != implied_status {
+        modifier.push(status2fen(status));
+    }
+    if modifier.is_empty() {
+        modifier
+    } else {
+        format!("[{}]", modifier)
+    }
+}
+
+fn find_en_passant_pawn(board: &Board, square: Pos, player: Color) -> Option<Pos> {
+    // the opponent's pawn that could have just leaped over square
+    LOS.iter().map(|dir| square + *dir).find(|pos| {
+        matches!(
+            board.get(*pos),
+            Some(Square::Piece(color, Piece::Pawn { orientation, .. }))
+                if *color != player && *pos == square + *orientation
+        )
+    })
+}
+
+fn en_passant_pawn(board: &Board, player: Color) -> Option<Pos> {
+    // the pawn of the opponent that just leaped, if there's only one and the en passant field can describe it
+    let mut pawns = (0..board.squares().len())
+        .map(|i| board.pos(i))
+        .filter(|pos| {
+            matches!(
+                board.get(*pos),
+                Some(Square::Piece(color, Piece::Pawn { status: PawnStatus::JustLeaped, .. }))
+                    if *color != player
+            )
+        });
+    let pawn = pawns.next()?;
+    if pawns.next().is_some() {
+        return None;
+    }
+    if let Some(Square::Piece(_, Piece::Pawn { orientation, .. })) = board.get(pawn) {
+        let square = pawn + *orientation * -1;
+        if board.get(square).is_some() && find_en_passant_pawn(board, square, player) == Some(pawn)
+        {
+            return Some(pawn);
+        }
+    }
+    None
+}
+
+pub fn board2fen(board: &Board, player: Color) -> String {
+    game2fen(&Game::new(board.clone(), player))
+}
+
+pub fn game2fen(game: &Game) -> String {
+    let board = &game.board;
+    let en_passant = en_passant_pawn(board, game.player);
+    let ranks = (0..board.height)
+        .map(|y| {
+            (0..board.width)
+                .map(|x| {
+                    let pos = Pos(x as i32, y as i32);
+                    match board.get(pos).unwrap() {
+                        Square::Empty => None,
+                        Square::Wall => Some("#".to_string()),
+                        Square::Piece(color, piece) => Some(format!(
+                            "{}{}",
+                            piece2fen(*color, *piece),
+                            pawn_modifier(board, pos, *color, en_passant)
+                        )),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let en_passant_square = match en_passant {
+        Some(pos) => {
+            if let Some(Square::Piece(_, Piece::Pawn { orientation, .. })) = board.get(pos) {
+                pos2pgn(board, pos + *orientation * -1)
+            } else {
+                unreachable!()
+            }
+        }
+        None => "-".to_string(),
+    };
+    // the game may not have started on a full move
+    let initial_player = game.initial().player;
+    let fullmove =
+        (game.history.len() + (initial_player == Color::Black) as usize) as u32 / 2 + game.fullmove;
+    let mut fen = format!(
+        "{} {} - {} {} {}",
+        write_runs(ranks),
+        match game.player {
+            Color::White => "w",
+            Color::Black => "b",
+        },
+        en_passant_square,
+        game.halfmove_clock,
+        fullmove
+    );
+    if board
+        .terrain
+        .iter()
+        .any(|terrain| *terrain != Terrain::Floor)
+    {
+        let ranks = (0..board.height)
+            .map(|y| {
+                (0..board.width)
+                    .map(|x| match board.terrain(Pos(x as i32, y as i32)) {
+                        Terrain::Floor => None,
+                        Terrain::Ice => Some("i".to_string()),
+                        Terrain::Lava => Some("l".to_string()),
+                        Terrain::Water => Some("w".to_string()),
+                        Terrain::Fortification => Some("f".to_string()),
+                        Terrain::Teleporter(link_pos) => {
+                            Some(format!("t[{}]", pos2pgn(board, link_pos)))
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        fen += " ";
+        fen += &write_runs(ranks);
+    }
+    fen
+}
+
+enum Token {
+    Run(usize),
+    Item(char, Option<String>),
+}
+
+fn read_runs(field: &str) -> Result<Vec<Vec<Token>>, FenError> {
+    // split ranks into tokens, checking they all have the same width
+    let mut ranks = Vec::new();
+    let mut expected = None;
+    for (rank, rank_str) in field.split('/').enumerate() {
+        if rank >= MAX_HEIGHT {
+            return Err(FenError::TooTall {
+                height: field.split('/').count(),
+            });
+        }
+        let mut tokens = Vec::new();
+        let mut width: usize = 0;
+        let mut chars = rank_str.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_ascii_digit() {
+                let mut number = c.to_string();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    number.push(digit);
+                }
+                let run: usize = number
+                    .parse()
+                    .map_err(|_| FenError::InvalidNumber(number.clone()))?;
+                width = width
+                    .checked_add(run)
+                    .ok_or_else(|| FenError::InvalidNumber(number.clone()))?;
+                tokens.push(Token::Run(run));
+            } else if c == '[' || c == ']' {
+                return Err(FenError::InvalidChar { rank: rank + 1, c });
+            } else {
+                let modifier = if chars.next_if_eq(&'[').is_some() {
+                    let mut modifier = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c) => modifier.push(c),
+                            None => return Err(FenError::InvalidPawnModifier(modifier)),
+                        }
+                    }
+                    Some(modifier)
+                } else {
+                    None
+                };
+                width += 1;
+                tokens.push(Token::Item(c, modifier));
+            }
+            if width > MAX_WIDTH {
+                return Err(FenError::TooLarge { width });
+            }
+        }
+        match expected {
+            None => expected = Some(width),
+            Some(expected) if expected != width => {
+                return Err(FenError::UnevenRank {
+                    rank: rank + 1,
+                    width,
+                    expected,
+                })
+            }
+            _ => {}
+        }
+        ranks.push(tokens);
+    }
+    Ok(ranks)
+}
+
+fn read_pawn_modifier(modifier: &str) -> Result<(Option<Pos>, Option<PawnStatus>), FenError> {
+    let invalid = || FenError::InvalidPawnModifier(modifier.to_string());
+    let (dir, status) = match modifier.char_indices().last() {
+        Some((i, '+')) => (&modifier[..i], Some(PawnStatus::CanLeap)),
+        Some((i, '!')) => (&modifier[..i], Some(PawnStatus::JustLeaped)),
+        Some((i, '-')) => (&modifier[..i], Some(PawnStatus::CannotLeap)),
+        _ => (modifier, None),
+    };
+    let orientation = if dir.is_empty() {
+        None
+    } else {
+        Some(
+            DIRS.iter()
+                .find(|(name, _)| *name == dir)
+                .ok_or_else(invalid)?
+                .1,
+        )
+    };
+    Ok((orientation, status))
+}
+
+fn read_number(field: Option<&str>, default: u32) -> Result<u32, FenError> {
+    match field {
+        Some(number) => number
+            .parse()
+            .map_err(|_| FenError::InvalidNumber(number.to_string())),
+        None => Ok(default),
+    }
+}
+
+fn read_terrain(board: &mut Board, field: &str) -> Result<(), FenError> {
+    let ranks = read_runs(field)?;
+    if ranks.len() != board.height || ranks.iter().any(|rank| rank_width(rank) != board.width) {
+        return Err(FenError::TerrainSize);
+    }
+    for (y, rank) in ranks.iter().enumerate() {
+        let mut x = 0;
+        for token in rank {
+            match token {
+                Token::Run(run) => x += run,
+                Token::Item(c, modifier) => {
+                    let pos = Pos(x as i32, y as i32);
+                    let terrain = match (c, modifier) {
+                        ('i', None) => Terrain::Ice,
+                        ('l', None) => Terrain::Lava,
+                        ('w', None) => Terrain::Water,
+                        ('f', None) => Terrain::Fortification,
+                        ('t', Some(square)) => Terrain::Teleporter(
+                            pgn2pos(board, square)
+                                .ok_or_else(|| FenError::InvalidSquare(square.clone()))?,
+                        ),
+                        _ => return Err(FenError::InvalidChar { rank: y + 1, c: *c }),
+                    };
+                    board.set_terrain(pos, terrain);
+                    x += 1;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rank_width(rank: &[Token]) -> usize {
+    rank.iter()
+        .map(|token| match token {
+            Token::Run(run) => *run,
+            Token::Item(..) => 1,
+        })
+        .sum()
+}
+
+pub fn fen2board(fen: &str) -> Result<(Board, Color), FenError> {
+    let game = fen2game(fen)?;
+    Ok((game.board, game.player))
+}
+
+pub fn fen2game(fen: &str) -> Result<Game, FenError> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() > 7 {
+        return Err(FenError::TooManyFields(fields.len()));
+    }
+    let placement = *fields.first().ok_or(FenError::MissingField("placement"))?;
+    let player = match *fields
+        .get(1)
+        .ok_or(FenError::MissingField("side to move"))?
+    {
+        "w" => Color::White,
+        "b" => Color::Black,
+        side => return Err(FenError::InvalidSide(side.to_string())),
+    };
+    let ranks = read_runs(placement)?;
+    let width = rank_width(&ranks[0]);
+    let mut board = Board::new(width, ranks.len());
+    let mut modifiers = Vec::new();
+    for (y, rank) in ranks.iter().enumerate() {
+        let mut x = 0;
+        for token in rank {
+            match token {
+                Token::Run(run) => x += run,
+                Token::Item(c, modifier) => {
+                    let pos = Pos(x as i32, y as i32);
+                    if *c == '#' && modifier.is_none() {
+                        board.set(pos, Square::Wall);
+                    } else if let Some((color, piece)) = fen2piece(*c) {
+                        if let Piece::Pawn { .. } = piece {
+                            modifiers.push((
+                                pos,
+                                read_pawn_modifier(modifier.as_deref().unwrap_or(""))?,
+                            ));
+                        } else if let Some(modifier) = modifier {
+                            return Err(FenError::InvalidPawnModifier(modifier.clone()));
+                        }
+                        board.set(pos, Square::Piece(color, piece));
+                    } else {
+                        return Err(FenError::InvalidChar { rank: y + 1, c: *c });
+                    }
+                    x += 1;
+                }
+            }
+        }
+    }
+    if let Some(terrain) = fields.get(6) {
+        read_terrain(&mut board, terrain)?;
+    }
+    // now that the board is complete we can deduce the pawns' default orientation and status
+    for (pos, (orientation, status)) in modifiers {
+        let (color, _) = board.get(pos).unwrap().piece().unwrap();
+        let orientation = orientation.unwrap_or_else(|| default_orientation(color));
+        let status = status.unwrap_or_else(|| default_status(&board, pos, orientation));
+        board.set(
+            pos,
+            Square::Piece(
+                color,
+                Piece::Pawn {
+                    orientation,
+                    status,
+                },
+            ),
+        );
+    }
+    // fields[2] is castling which doesn't exist in DungeonChess
+    if let Some(en_passant) = fields.get(3).filter(|field| **field != "-") {
+        let square = pgn2pos(&board, en_passant)
+            .ok_or_else(|| FenError::InvalidSquare(en_passant.to_string()))?;
+        let pawn_pos = find_en_passant_pawn(&board, square, player)
+            .ok_or_else(|| FenError::NoEnPassantPawn(en_passant.to_string()))?;
+        if let Some(Square::Piece(color, Piece::Pawn { orientation, .. })) = board.get(pawn_pos) {
+            let pawn = Square::Piece(
+                *color,
+                Piece::Pawn {
+                    orientation: *orientation,
+                    status: PawnStatus::JustLeaped,
+                },
+            );
+            board.set(pawn_pos, pawn);
+        }
+    }
+    let mut game = Game::new(board, player);
+    game.halfmove_clock = read_number(fields.get(4).copied(), 0)?;
+    game.fullmove = read_number(fields.get(5).copied(), 1)?;
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{
+            tests::{random_board, random_dims},
+            Board, Square, Terrain,
+        },
+        fen::{board2fen, fen2board, fen2game, game2fen, FenError, DIRS, STANDARD_FEN},
+        game::{standard_board, Game},
+        moves::Move,
+        piece::{Color, PawnStatus, Piece},
+        pos::Pos,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn standard_position() {
+        assert!(board2fen(&standard_board(), Color::White) == STANDARD_FEN);
+        let (board, player) = fen2board(STANDARD_FEN).unwrap();
+        assert!(board == standard_board());
+        assert!(player == Color::White);
+        // the shortest form is fine too
+        let (board, _) = fen2board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w").unwrap();
+        assert!(board == standard_board());
+    }
+
+    #[test]
+    fn en_passant() {
+        let mut game = Game::new(standard_board(), Color::White);
+        game.make_move(Move::new(Pos(4, 6), Pos(4, 4)));
+        let fen = game2fen(&game);
+        assert!(fen == "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b - e3 0 1");
+        let parsed = fen2game(&fen).unwrap();
+        assert!(parsed.board == game.board);
+        game.make_move(Move::new(Pos(0, 1), Pos(0, 2)));
+        assert!(game2fen(&game).ends_with(" w - - 0 2"));
+        assert!(fen2game(&game2fen(&game)).unwrap().board == game.board);
+    }
+
+    #[test]
+    fn fullmove() {
+        // the game goes on from the move the fen was written at
+        let fen = "4k3/8/8/8/8/8/8/4K3 b - - 5 12";
+        let mut game = fen2game(fen).unwrap();
+        assert!(game.fullmove == 12 && game2fen(&game) == fen);
+        game.make_move(Move::new(Pos(4, 0), Pos(3, 0)));
+        assert!(game2fen(&game).ends_with(" w - - 6 13"));
+        assert!(game2fen(&game.initial()) == fen);
+    }
+
+    #[test]
+    fn long_orientation() {
+        // a pawn facing further than a step is written with the direction it faces
+        let mut board = Board::new(3, 3);
+        let pawn = Piece::Pawn {
+            orientation: Pos(0, 2),
+            status: PawnStatus::CannotLeap,
+        };
+        board.set(Pos(1, 0), Square::Piece(Color::White, pawn));
+        assert!(board2fen(&board, Color::White).starts_with("1P[s]1/3/3 w"));
+    }
+
+    #[test]
+    fn dungeon_position() {
+        let fen = "k1#1/P[e]2K/4 w - - 3 1 i1lw/t[d1]3/3t[a2]";
+        let game = fen2game(fen).unwrap();
+        assert!(game.board.width == 4 && game.board.height == 3);
+        assert!(game.board.get(Pos(2, 0)) == Some(&Square::Wall));
+        assert!(
+            game.board.get(Pos(0, 1))
+                == Some(&Square::Piece(
+                    Color::White,
+                    Piece::Pawn {
+                        orientation: Pos(1, 0),
+                        status: PawnStatus::CannotLeap
+                    }
+                ))
+        );
+        assert!(game.board.terrain(Pos(0, 1)) == Terrain::Teleporter(Pos(3, 2)));
+        assert!(game.halfmove_clock == 3);
+        assert!(game2fen(&game) == fen);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(fen2board("") == Err(FenError::MissingField("placement")));
+        assert!(fen2board("8/8") == Err(FenError::MissingField("side to move")));
+        assert!(fen2board("8/8 x") == Err(FenError::InvalidSide("x".to_string())));
+        assert!(
+            fen2board("8/7 w")
+                == Err(FenError::UnevenRank {
+                    rank: 2,
+                    width: 7,
+                    expected: 8
+                })
+        );
+        assert!(fen2board("8/7x w") == Err(FenError::InvalidChar { rank: 2, c: 'x' }));
+        assert!(fen2board("P[up]7/8 w") == Err(FenError::InvalidPawnModifier("up".to_string())));
+        assert!(fen2board("8/8 w - z9") == Err(FenError::InvalidSquare("z9".to_string())));
+        assert!(
+            fen2board("8/8 w - a-2147483648 0 1")
+                == Err(FenError::InvalidSquare("a-2147483648".to_string()))
+        );
+        assert!(fen2board("8/8 w - e1") == Err(FenError::NoEnPassantPawn("e1".to_string())));
+        assert!(fen2board("8/8 w - - x") == Err(FenError::InvalidNumber("x".to_string())));
+        assert!(fen2board("8/8 w - - 0 1 8") == Err(FenError::TerrainSize));
+        assert!(fen2board("27/27/27 w") == Err(FenError::TooLarge { width: 27 }));
+        assert!(
+            fen2board("18446744073709551615k/8 w")
+                == Err(FenError::TooLarge {
+                    width: 18446744073709551615
+                })
+        );
+        assert!(fen2board("20k20k/8 w") == Err(FenError::TooLarge { width: 41 }));
+        assert!(
+            fen2board("k18446744073709551615/8 w")
+                == Err(FenError::InvalidNumber("18446744073709551615".to_string()))
+        );
+        assert!(
+            fen2board(&format!("{} w", ["8"; 65].join("/")))
+                == Err(FenError::TooTall { height: 65 })
+        );
+        assert!(
+            FenError::MissingField("side to move").to_string() == "missing the side to move field"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let mut board = random_board(&mut rng, width, height);
+            for i in 0..width * height {
+                let pos = board.pos(i);
+                match board.squares()[i] {
+                    Square::Piece(color, Piece::Pawn { .. }) => {
+                        let status = [
+                            PawnStatus::CanLeap,
+                            PawnStatus::JustLeaped,
+                            PawnStatus::CannotLeap,
+                        ][rng.gen_range(0..3)];
+                        let orientation = DIRS[rng.gen_range(0..8)].1;
+                        board.set(
+                            pos,
+                            Square::Piece(
+                                color,
+                                Piece::Pawn {
+                                    orientation,
+                                    status,
+                                },
+                            ),
+                        );
+                    }
+                    Square::Empty if rng.gen_bool(0.1) => board.set(pos, Square::Wall),
+                    _ => {}
+                }
+                match rng.gen_range(0..10) {
+                    0 => board.set_terrain(pos, Terrain::Ice),
+                    1 => board.set_terrain(pos, Terrain::Lava),
+                    2 => board.set_terrain(pos, Terrain::Water),
+                    3 => board.set_terrain(pos, Terrain::Fortification),
+                    4 => board.set_terrain(
+                        pos,
+                        Terrain::Teleporter(board.pos(rng.gen_range(0..width * height))),
+                    ),
+                    _ => {}
+                }
+            }
+            for player in [Color::White, Color::Black] {
+                let fen = board2fen(&board, player);
+                let (parsed, parsed_player) = fen2board(&fen).unwrap();
+                assert!(parsed == board, "{}", fen);
+                assert!(parsed_player == player);
+            }
+        }
+    }
+}
@@ -1,6 +1,188 @@
-use crate::board::Board;
+use crate::board::{Board, Square};
+use crate::moves::{Move, MoveList};
 use crate::piece::{Color, PawnStatus, Piece};
 use crate::pos::Pos;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    // holds the winner
+    Checkmate(Color),
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+}
+
+impl Outcome {
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            Outcome::Checkmate(color) => Some(*color),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Checkmate(Color::White) => write!(f, "White wins by checkmate"),
+            Outcome::Checkmate(Color::Black) => write!(f, "Black wins by checkmate"),
+            Outcome::Stalemate => write!(f, "Draw by stalemate"),
+            Outcome::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            Outcome::FiftyMoveRule => write!(f, "Draw by the fifty-move rule"),
+            Outcome::ThreefoldRepetition => write!(f, "Draw by threefold repetition"),
+        }
+    }
+}
+
+// what it takes to restore the game as it was before a move
+#[derive(Clone)]
+struct Undo {
+    board: Board,
+    halfmove_clock: u32,
+    captured: usize,
+}
+
+#[derive(Clone)]
+pub struct Game {
+    pub board: Board,
+    pub player: Color,
+    // number of plies since the last capture or pawn move
+    pub halfmove_clock: u32,
+    // the full move the game started on, games set up from a FEN can start later than the first
+    pub fullmove: u32,
+    // every move played so far, in order
+    pub history: Vec<Move>,
+    // every piece taken so far, in the order they were captured
+    pub captured: Vec<(Color, Piece)>,
+    // one entry per move of history
+    undos: Vec<Undo>,
+    // moves that were undone, the last one being the next to redo
+    redos: Vec<Move>,
+}
+
+impl Game {
+    pub fn new(board: Board, player: Color) -> Self {
+        Self {
+            board,
+            player,
+            halfmove_clock: 0,
+            fullmove: 1,
+            history: Vec::new(),
+            captured: Vec::new(),
+            undos: Vec::new(),
+            redos: Vec::new(),
+        }
+    }
+
+    pub fn legal_moves(&self) -> MoveList {
+        self.board.moves(self.player, true)
+    }
+
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.legal_moves().contains(&mv)
+    }
+
+    pub fn initial(&self) -> Game {
+        // the game as it was before the first move of its history
+        let mut game = Game::new(self.board.clone(), self.player);
+        game.halfmove_clock = self.halfmove_clock;
+        game.fullmove = self.fullmove;
+        if let Some(undo) = self.undos.first() {
+            game.board = undo.board.clone();
+            game.halfmove_clock = undo.halfmove_clock;
+            if !self.history.len().is_multiple_of(2) {
+                game.player = self.player.next();
+            }
+        }
+        game
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().copied()
+    }
+
+    fn captures(&self, mv: Move) -> Vec<(Color, Piece)> {
+        [Some(mv.to()), mv.take()]
+            .iter()
+            .flatten()
+            .filter_map(|pos| self.board.get(*pos).and_then(|square| square.piece()))
+            .collect()
+    }
+
+    fn play(&mut self, mv: Move) {
+        let captures = self.captures(mv);
+        self.undos.push(Undo {
+            board: self.board.clone(),
+            halfmove_clock: self.halfmove_clock,
+            captured: self.captured.len(),
+        });
+        if !captures.is_empty()
+            || matches!(
+                self.board.get(mv.from()),
+                Some(Square::Piece(_, Piece::Pawn { .. }))
+            )
+        {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        self.captured.extend(captures);
+        self.board = self.board.play(self.player, mv);
+        self.player = self.player.next();
+        self.history.push(mv);
+    }
+
+    pub fn make_move(&mut self, mv: Move) {
+        // NOTE: the move is not checked, use is_legal beforehand if it comes from an untrusted source
+        self.redos.clear();
+        self.play(mv);
+    }
+
+    pub fn undo(&mut self) -> Option<Move> {
+        let undo = self.undos.pop()?;
+        let last_move = self.history.pop().unwrap();
+        self.board = undo.board;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.captured.truncate(undo.captured);
+        self.player = self.player.next();
+        self.redos.push(last_move);
+        Some(last_move)
+    }
+
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.redos.pop()?;
+        self.play(mv);
+        Some(mv)
+    }
+
+    pub fn repetitions(&self) -> usize {
+        // positions can only repeat since the last irreversible move, with the same side to move
+        std::iter::once(&self.board)
+            .chain(self.undos.iter().rev().map(|undo| &undo.board))
+            .take(self.halfmove_clock as usize + 1)
+            .step_by(2)
+            // comparing hashes first is cheap, the full comparison rules out collisions
+            .filter(|board| {
+                board.hash(self.player) == self.board.hash(self.player) && **board == self.board
+            })
+            .count()
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        if let Some(outcome) = self.board.outcome(self.player) {
+            return Some(outcome);
+        }
+        if self.halfmove_clock >= 100 {
+            return Some(Outcome::FiftyMoveRule);
+        }
+        if self.repetitions() >= 3 {
+            return Some(Outcome::ThreefoldRepetition);
+        }
+        None
+    }
+}
 
 pub fn standard_board() -> Board {
     let mut board = Board::new(8, 8);
@@ -25,9 +207,9 @@ pub fn standard_board() -> Board {
         )
         .enumerate()
     {
-        board.squares[i] = Some((Color::Black, *piece));
+        board.set(board.pos(i), Square::Piece(Color::Black, *piece));
     }
-    let len_squares = board.squares.len();
+    let len_squares = board.squares().len();
     for (i, piece) in pieces
         .iter()
         .rev()
@@ -40,23 +222,153 @@ pub fn standard_board() -> Board {
         )
         .enumerate()
     {
-        board.squares[len_squares - i - 1] = Some((Color::White, *piece));
+        board.set(
+            board.pos(len_squares - i - 1),
+            Square::Piece(Color::White, *piece),
+        );
     }
     board
 }
 
 pub fn invert_color(board: Board) -> Board {
-    let mut inverted = Board::new(board.width, board.height);
-    inverted.squares = board
-        .squares
-        .iter()
-        .map(|square| {
-            if let Some((color, piece)) = square {
-                Some((color.next(), *piece))
-            } else {
-                None
-            }
-        })
-        .collect();
+    let mut inverted = board.clone();
+    for (i, square) in board.squares().iter().enumerate() {
+        if let Square::Piece(color, piece) = square {
+            inverted.set(board.pos(i), Square::Piece(color.next(), *piece));
+        }
+    }
     inverted
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, Square},
+        game::{standard_board, Game, Outcome},
+        moves::Move,
+        piece::{Color, Piece},
+        pos::Pos,
+        unit::UnitId,
+    };
+
+    #[test]
+    fn fools_mate() {
+        let mut game = Game::new(standard_board(), Color::White);
+        game.make_move(Move::new(Pos(5, 6), Pos(5, 5)));
+        game.make_move(Move::new(Pos(4, 1), Pos(4, 3)));
+        game.make_move(Move::new(Pos(6, 6), Pos(6, 4)));
+        assert!(game.outcome().is_none());
+        game.make_move(Move::new(Pos(3, 0), Pos(7, 4)));
+        assert!(game.outcome() == Some(Outcome::Checkmate(Color::Black)));
+        assert!(game.outcome().unwrap().winner() == Some(Color::Black));
+    }
+
+    #[test]
+    fn stalemate() {
+        let mut board = Board::new(8, 8);
+        board.set(Pos(7, 0), Square::Piece(Color::Black, Piece::King));
+        board.set(Pos(6, 2), Square::Piece(Color::White, Piece::Queen));
+        board.set(Pos(5, 1), Square::Piece(Color::White, Piece::King));
+        assert!(board.outcome(Color::Black) == Some(Outcome::Stalemate));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let mut board = Board::new(8, 8);
+        board.set(Pos(0, 0), Square::Piece(Color::Black, Piece::King));
+        board.set(Pos(7, 7), Square::Piece(Color::White, Piece::King));
+        assert!(board.insufficient_material());
+        board.set(Pos(3, 3), Square::Piece(Color::White, Piece::Bishop));
+        assert!(board.insufficient_material());
+        board.set(Pos(5, 5), Square::Piece(Color::Black, Piece::Bishop));
+        assert!(board.insufficient_material());
+        board.set(Pos(5, 4), Square::Piece(Color::Black, Piece::Bishop));
+        assert!(!board.insufficient_material());
+        board.set(Pos(5, 4), Square::Piece(Color::Black, Piece::Rook));
+        assert!(board.outcome(Color::White).is_none());
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = Game::new(standard_board(), Color::White);
+        for _ in 0..2 {
+            assert!(game.outcome().is_none());
+            game.make_move(Move::new(Pos(6, 7), Pos(5, 5)));
+            game.make_move(Move::new(Pos(6, 0), Pos(5, 2)));
+            game.make_move(Move::new(Pos(5, 5), Pos(6, 7)));
+            game.make_move(Move::new(Pos(5, 2), Pos(6, 0)));
+        }
+        assert!(game.repetitions() == 3);
+        assert!(game.outcome() == Some(Outcome::ThreefoldRepetition));
+        // rooks that swapped squares still make the same position, whichever units they are
+        let mut board = Board::new(8, 8);
+        board.set(Pos(7, 0), Square::Piece(Color::Black, Piece::King));
+        board.set(Pos(4, 5), Square::Piece(Color::White, Piece::King));
+        for (i, pos) in [Pos(0, 7), Pos(1, 7)].iter().enumerate() {
+            board.set(*pos, Square::Piece(Color::White, Piece::Rook));
+            board.set_unit(*pos, Some(UnitId(i as u32)));
+        }
+        let mut game = Game::new(board, Color::White);
+        for (from, to) in [
+            (Pos(0, 7), Pos(0, 6)),
+            (Pos(7, 0), Pos(6, 0)),
+            (Pos(1, 7), Pos(0, 7)),
+            (Pos(6, 0), Pos(7, 0)),
+            (Pos(0, 6), Pos(1, 6)),
+            (Pos(7, 0), Pos(6, 0)),
+            (Pos(1, 6), Pos(1, 7)),
+            (Pos(6, 0), Pos(7, 0)),
+        ] {
+            game.make_move(Move::new(from, to));
+        }
+        assert!(game.board.unit(Pos(0, 7)) == Some(UnitId(1)));
+        assert!(game.repetitions() == 2);
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let mut game = Game::new(standard_board(), Color::White);
+        game.halfmove_clock = 99;
+        game.make_move(Move::new(Pos(6, 7), Pos(5, 5)));
+        assert!(game.outcome() == Some(Outcome::FiftyMoveRule));
+        // a pawn move resets the clock
+        game.make_move(Move::new(Pos(4, 1), Pos(4, 3)));
+        assert!(game.halfmove_clock == 0);
+        assert!(game.outcome().is_none());
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut game = Game::new(standard_board(), Color::White);
+        game.make_move(Move::new(Pos(4, 6), Pos(4, 4)));
+        game.make_move(Move::new(Pos(3, 1), Pos(3, 3)));
+        game.make_move(Move::new(Pos(6, 7), Pos(5, 5)));
+        let before_capture = game.board.clone();
+        game.make_move(Move::new(Pos(3, 3), Pos(4, 4)));
+        assert!(game.captured.len() == 1);
+        assert!(game.history.len() == 4);
+        assert!(game.halfmove_clock == 0);
+        assert!(game.undo() == Some(Move::new(Pos(3, 3), Pos(4, 4))));
+        assert!(game.board == before_capture);
+        assert!(game.player == Color::Black);
+        assert!(game.captured.is_empty());
+        assert!(game.halfmove_clock == 1);
+        assert!(game.redo().is_some());
+        assert!(game.captured == vec![(Color::White, game.captured[0].1)]);
+        assert!(game.redo().is_none());
+        while game.undo().is_some() {}
+        assert!(game.board == standard_board());
+        assert!(game.history.is_empty());
+        // playing a new move forgets the undone ones
+        game.make_move(Move::new(Pos(1, 7), Pos(2, 5)));
+        assert!(game.redo().is_none());
+    }
+
+    #[test]
+    fn legal_moves() {
+        let game = Game::new(standard_board(), Color::White);
+        assert!(game.legal_moves().len() == 20);
+        assert!(game.is_legal(Move::new(Pos(4, 6), Pos(4, 4))));
+        assert!(!game.is_legal(Move::new(Pos(4, 6), Pos(4, 3))));
+    }
+}
@@ -0,0 +1,19 @@
+pub mod ai;
+pub mod attacks;
+pub mod board;
+pub mod cli;
+pub mod deploy;
+pub mod dungeon;
+pub mod eval;
+pub mod fen;
+pub mod game;
+pub mod moves;
+pub mod perft;
+pub mod pgn;
+pub mod piece;
+pub mod pos;
+pub mod profile;
+pub mod room;
+pub mod transposition;
+pub mod uci;
+pub mod unit;
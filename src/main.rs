@@ -1,14 +1,100 @@
-mod ai;
-mod board;
-mod game;
-mod pgn;
-mod piece;
-mod pos;
-use ai::auto_play;
-use game::standard_board;
-use piece::Color;
+use dungeon_chess::ai::auto_play;
+use dungeon_chess::cli::Session;
+use dungeon_chess::eval::{ConfigEvaluator, Evaluator, MaterialEvaluator};
+use dungeon_chess::game::standard_board;
+use dungeon_chess::piece::Color;
+use dungeon_chess::profile::{AiProfile, Difficulty, Personality};
+use rand::{rngs::StdRng, SeedableRng};
+
+const USAGE: &str = "usage: dungeon_chess [play] [--black] [--depth <n>] [--eval <file>]
+                     [--difficulty <easy|normal|hard|expert>] [--personality <balanced|aggressive|defensive|cowardly>] [--seed <n>]
+       dungeon_chess auto [<depth>]";
 
 fn main() {
-    let pgn_moves = auto_play(standard_board(), Color::White, 5);
-    println!("{}", pgn_moves);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice() {
+        ["auto"] => println!("{}", auto_play(standard_board(), Color::White, 5)),
+        ["auto", depth] => match depth.parse() {
+            Ok(depth) if depth > 0 => {
+                println!("{}", auto_play(standard_board(), Color::White, depth))
+            }
+            _ => eprintln!("{}", USAGE),
+        },
+        _ => {
+            let mut human = Color::White;
+            let mut depth = 4;
+            let mut evaluator: Box<dyn Evaluator> = Box::new(MaterialEvaluator);
+            let mut difficulty = None;
+            let mut personality = None;
+            let mut seed = None;
+            let mut options = args.iter().skip_while(|arg| **arg == "play");
+            while let Some(option) = options.next() {
+                match *option {
+                    "--black" => human = Color::Black,
+                    "--depth" => match options.next().map(|n| n.parse()) {
+                        Some(Ok(n)) if n > 0 => depth = n,
+                        _ => {
+                            eprintln!("{}", USAGE);
+                            return;
+                        }
+                    },
+                    "--difficulty" => match options.next().map(|name| name.parse()) {
+                        Some(Ok(level)) => difficulty = Some(level),
+                        _ => {
+                            eprintln!("{}", USAGE);
+                            return;
+                        }
+                    },
+                    "--personality" => match options.next().map(|name| name.parse()) {
+                        Some(Ok(kind)) => personality = Some(kind),
+                        _ => {
+                            eprintln!("{}", USAGE);
+                            return;
+                        }
+                    },
+                    "--seed" => match options.next().map(|n| n.parse()) {
+                        Some(Ok(n)) => seed = Some(n),
+                        _ => {
+                            eprintln!("{}", USAGE);
+                            return;
+                        }
+                    },
+                    "--eval" => match options.next().map(ConfigEvaluator::load) {
+                        Some(Ok(config_evaluator)) => evaluator = Box::new(config_evaluator),
+                        Some(Err(err)) => {
+                            eprintln!("{}", err);
+                            return;
+                        }
+                        None => {
+                            eprintln!("{}", USAGE);
+                            return;
+                        }
+                    },
+                    _ => {
+                        eprintln!("{}", USAGE);
+                        return;
+                    }
+                }
+            }
+            let mut session = Session::new(std::io::stdout(), human, depth);
+            if difficulty.is_some() || personality.is_some() {
+                let mut profile = AiProfile::new(
+                    difficulty.unwrap_or(Difficulty::Normal),
+                    personality.unwrap_or(Personality::Balanced),
+                );
+                profile.evaluator = evaluator;
+                session.profile = Some(profile);
+            } else {
+                session.evaluator = evaluator;
+            }
+            // a fresh seed is shown so that the game can be replayed with --seed
+            let seed = seed.unwrap_or_else(rand::random);
+            println!("Seed: {}", seed);
+            session.rng = StdRng::seed_from_u64(seed);
+            if let Err(err) = session.run(std::io::stdin().lock()) {
+                eprintln!("{}", err);
+            }
+        }
+    }
 }
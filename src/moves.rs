@@ -0,0 +1,313 @@
+// A move is 8 bytes and Copy, and move lists live on the stack,
+// so that generating and searching moves doesn't allocate
+use crate::piece::Piece;
+use crate::pos::Pos;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+// what move generation knows about a move besides its squares, moves built by hand don't have them
+const CAPTURE: u8 = 1;
+const EN_PASSANT: u8 = 1 << 1;
+// a pawn moving 2 squares at once
+const LEAP: u8 = 1 << 2;
+
+const fn square(pos: Pos) -> u16 {
+    // a square in 2 bytes, the column then the row, boards are never bigger than 256 squares across
+    pos.0 as u16 | (pos.1 as u16) << 8
+}
+
+const fn square_pos(square: u16) -> Pos {
+    Pos((square & 0xff) as i32, (square >> 8) as i32)
+}
+
+fn promotion_code(piece: Piece) -> u8 {
+    match piece {
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        Piece::King => 5,
+        Piece::Pawn { .. } => panic!("a pawn can't promote to a pawn"),
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Move {
+    from: u16,
+    to: u16,
+    // the square of a piece captured away from the destination, for en passant
+    take: u16,
+    // 0 when the move doesn't promote
+    promotion: u8,
+    flags: u8,
+}
+
+impl Move {
+    pub const fn new(from: Pos, to: Pos) -> Self {
+        Self {
+            from: square(from),
+            to: square(to),
+            take: 0,
+            promotion: 0,
+            flags: 0,
+        }
+    }
+
+    pub fn en_passant(from: Pos, to: Pos, take: Pos) -> Self {
+        Self {
+            take: square(take),
+            flags: CAPTURE | EN_PASSANT,
+            ..Self::new(from, to)
+        }
+    }
+
+    pub fn promoting(self, piece: Piece) -> Self {
+        Self {
+            promotion: promotion_code(piece),
+            ..self
+        }
+    }
+
+    pub fn capturing(self) -> Self {
+        Self {
+            flags: self.flags | CAPTURE,
+            ..self
+        }
+    }
+
+    pub fn leaping(self) -> Self {
+        Self {
+            flags: self.flags | LEAP,
+            ..self
+        }
+    }
+
+    pub fn from(&self) -> Pos {
+        square_pos(self.from)
+    }
+
+    pub fn to(&self) -> Pos {
+        square_pos(self.to)
+    }
+
+    pub fn take(&self) -> Option<Pos> {
+        self.is_en_passant().then(|| square_pos(self.take))
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        match self.promotion {
+            1 => Some(Piece::Knight),
+            2 => Some(Piece::Bishop),
+            3 => Some(Piece::Rook),
+            4 => Some(Piece::Queen),
+            5 => Some(Piece::King),
+            _ => None,
+        }
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.flags & CAPTURE != 0
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags & EN_PASSANT != 0
+    }
+
+    pub fn is_leap(&self) -> bool {
+        self.flags & LEAP != 0
+    }
+
+    fn key(&self) -> (u16, u16, Option<u16>, u8) {
+        // what tells moves apart, a capture or a leap follows from the squares
+        (
+            self.from,
+            self.to,
+            self.is_en_passant().then_some(self.take),
+            self.promotion,
+        )
+    }
+}
+
+impl PartialEq for Move {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Move {}
+
+impl Hash for Move {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Debug for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Move")
+            .field("from", &self.from())
+            .field("to", &self.to())
+            .field("take", &self.take())
+            .field("promotion", &self.promotion())
+            .field("capture", &self.is_capture())
+            .field("leap", &self.is_leap())
+            .finish()
+    }
+}
+
+// most positions have well under a hundred moves, the rare bigger ones spill to the heap
+pub const MOVE_LIST_CAPACITY: usize = 128;
+
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MOVE_LIST_CAPACITY],
+    len: usize,
+    // holds every move instead once the list outgrew its capacity
+    spilled: Vec<Move>,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self {
+            moves: [Move::new(Pos(0, 0), Pos(0, 0)); MOVE_LIST_CAPACITY],
+            len: 0,
+            spilled: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        if self.len < MOVE_LIST_CAPACITY {
+            self.moves[self.len] = mv;
+            self.len += 1;
+        } else {
+            if self.spilled.is_empty() {
+                self.spilled.extend_from_slice(&self.moves);
+            }
+            self.spilled.push(mv);
+        }
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        if !self.spilled.is_empty() {
+            self.spilled.retain(keep);
+            if self.spilled.is_empty() {
+                self.len = 0;
+            }
+            return;
+        }
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        if self.spilled.is_empty() {
+            &self.moves[..self.len]
+        } else {
+            &self.spilled
+        }
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        if self.spilled.is_empty() {
+            &mut self.moves[..self.len]
+        } else {
+            &mut self.spilled
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<I: IntoIterator<Item = Move>>(&mut self, moves: I) {
+        moves.into_iter().for_each(|mv| self.push(mv));
+    }
+}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<I: IntoIterator<Item = Move>>(moves: I) -> Self {
+        let mut list = Self::new();
+        list.extend(moves);
+        list
+    }
+}
+
+impl PartialEq for MoveList {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        moves::{Move, MoveList, MOVE_LIST_CAPACITY},
+        piece::Piece,
+        pos::Pos,
+    };
+
+    #[test]
+    fn spill() {
+        let mv = |i: usize| Move::new(Pos(0, 0), Pos(i as i32, 1));
+        let mut list: MoveList = (0..MOVE_LIST_CAPACITY).map(mv).collect();
+        assert!(list.len() == MOVE_LIST_CAPACITY);
+        list.push(mv(MOVE_LIST_CAPACITY));
+        assert!(list.len() == MOVE_LIST_CAPACITY + 1);
+        assert!(list.iter().enumerate().all(|(i, m)| *m == mv(i)));
+        list.retain(|m| m.to().0 % 2 == 0);
+        assert!(list.len() == MOVE_LIST_CAPACITY / 2 + 1);
+        list.retain(|_| false);
+        assert!(list.is_empty());
+        let mut small: MoveList = (0..10).map(mv).collect();
+        small.retain(|m| m.to().0 >= 5);
+        assert!(small[..] == (5..10).map(mv).collect::<Vec<_>>()[..]);
+        let promotion = mv(0).promoting(Piece::Queen);
+        assert!(promotion.promotion() == Some(Piece::Queen) && promotion.take().is_none());
+    }
+
+    #[test]
+    fn compact() {
+        assert!(std::mem::size_of::<Move>() == 8);
+        let mv = Move::en_passant(Pos(4, 3), Pos(3, 2), Pos(3, 3));
+        assert!(mv.from() == Pos(4, 3) && mv.to() == Pos(3, 2) && mv.take() == Some(Pos(3, 3)));
+        assert!(mv.is_capture() && mv.is_en_passant() && !mv.is_leap());
+        let leap = Move::new(Pos(11, 10), Pos(11, 8)).leaping();
+        assert!(leap.is_leap() && !leap.is_capture() && leap.to() == Pos(11, 8));
+        // the flags come from the board, the same squares are the same move
+        assert!(Move::new(Pos(0, 0), Pos(0, 1)).capturing() == Move::new(Pos(0, 0), Pos(0, 1)));
+        assert!(leap.promoting(Piece::Rook) != leap);
+    }
+}
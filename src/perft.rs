@@ -0,0 +1,100 @@
+// Counts the leaves of the legal move tree, to check move generation against known node counts
+use crate::board::Board;
+use crate::moves::Move;
+use crate::piece::Color;
+
+pub fn perft(board: &Board, color: Color, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.moves(color, true);
+    // the leaves don't need to be played to be counted
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .iter()
+        .map(|mv| perft(&board.play(color, *mv), color.next(), depth - 1))
+        .sum()
+}
+
+pub fn divide(board: &Board, color: Color, depth: u32) -> Vec<(Move, u64)> {
+    // the leaf count below each legal move, to find which move disagrees with a reference
+    board
+        .moves(color, true)
+        .iter()
+        .map(|mv| {
+            let board = board.play(color, *mv);
+            (*mv, perft(&board, color.next(), depth.saturating_sub(1)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Square,
+        fen::{fen2board, STANDARD_FEN},
+        moves::Move,
+        perft::{divide, perft},
+        pos::Pos,
+    };
+
+    fn check(fen: &str, counts: &[u64]) {
+        let (board, color) = fen2board(fen).unwrap();
+        // the flags of the generated moves agree with the board
+        for mv in board.moves(color, true).iter() {
+            let capture =
+                mv.take().is_some() || matches!(board.get(mv.to()), Some(Square::Piece(..)));
+            assert!(mv.is_capture() == capture);
+        }
+        for (depth, count) in counts.iter().enumerate() {
+            let nodes = perft(&board, color, depth as u32 + 1);
+            assert!(
+                nodes == *count,
+                "{} at depth {}: {} != {}",
+                fen,
+                depth + 1,
+                nodes,
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn standard() {
+        check(STANDARD_FEN, &[20, 400, 8902, 197281]);
+        // "position 3" of the chess programming wiki, which has no castling
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn en_passant() {
+        // exd6 is the only capture
+        check("k7/8/8/3pP3/8/8/8/7K w - d6 0 1", &[5, 19, 112, 689]);
+        // the pawn that just leaped can't be taken anymore after a waiting move
+        check("k7/8/8/3pP3/8/8/8/7K w - - 0 1", &[4, 16]);
+    }
+
+    #[test]
+    fn sideways_pawns() {
+        // a pawn facing east leaps next to a black pawn, which can take it en passant by going to c3
+        check("k7/8/8/8/P[e+]2p4/8/8/7K w - - 0 1", &[5, 21, 129, 803]);
+        // a pawn facing the edge it stands on is stuck, one facing south promotes on the first rank
+        // with check, and the wall blocks the leap of the black pawn facing east
+        check(
+            "k7/p[e+]1#5/8/8/8/8/P[w]3P[n]2P[s]/4K3 w - - 0 1",
+            &[8, 23, 229, 822],
+        );
+        let (board, color) = fen2board("k7/8/8/8/P[e+]2p4/8/8/7K w - - 0 1").unwrap();
+        let leap = Move::new(Pos(0, 4), Pos(2, 4));
+        let (mv, nodes) = divide(&board, color, 2)
+            .into_iter()
+            .find(|(mv, _)| *mv == leap)
+            .unwrap();
+        assert!(nodes == 5 && mv.is_leap());
+    }
+}
@@ -1,12 +1,17 @@
-use crate::piece::{Action, Piece};
+use crate::board::{Board, Square};
+use crate::fen::{fen2game, game2fen, FenError};
+use crate::game::{standard_board, Game, Outcome};
+use crate::moves::Move;
+use crate::piece::{Color, Piece};
 use crate::pos::Pos;
+use std::fmt::Display;
 
 fn piece2pgn(piece: Piece) -> &'static str {
     match piece {
         Piece::Pawn {
             orientation: _,
             status: _,
-        } => "p",
+        } => "",
         Piece::Knight => "N",
         Piece::Bishop => "B",
         Piece::Rook => "R",
@@ -15,19 +20,606 @@ fn piece2pgn(piece: Piece) -> &'static str {
     }
 }
 
-fn pos2pgn(pos: Pos) -> String {
-    let letters = ["a", "b", "c", "d", "e", "f", "g", "h"];
-    format!("{}{}", letters[pos.0 as usize], 8 - pos.1)
+pub fn pos2pgn(board: &Board, pos: Pos) -> String {
+    // files are lettered from a to z, so boards are at most 26 squares wide
+    let file = (b'a' + pos.0 as u8) as char;
+    format!("{}{}", file, board.height as i32 - pos.1)
 }
 
-pub fn move2pgn(pos: Pos, actions: &Vec<Action>) -> String {
+pub fn pgn2pos(board: &Board, pgn: &str) -> Option<Pos> {
+    let mut chars = pgn.chars();
+    let file = chars.next()?;
+    if !file.is_ascii_lowercase() {
+        return None;
+    }
+    // only digits, a sign or a huge rank is not a square of any board
+    let rank = chars.as_str();
+    if rank.is_empty() || !rank.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let rank: u16 = rank.parse().ok()?;
+    let y = board.height.checked_sub(rank as usize)?;
+    let pos = Pos(file as i32 - 'a' as i32, y as i32);
+    board.get(pos)?;
+    Some(pos)
+}
+
+fn pgn2piece(c: char) -> Option<Piece> {
+    match c {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+fn same_kind(piece1: Piece, piece2: Piece) -> bool {
+    // pawns are the same kind of piece whatever their orientation and status
+    std::mem::discriminant(&piece1) == std::mem::discriminant(&piece2)
+}
+
+fn is_capture(board: &Board, mv: Move) -> bool {
+    mv.take().is_some() || matches!(board.get(mv.to()), Some(Square::Piece(..)))
+}
+
+pub fn move2san(board: &Board, color: Color, mv: Move) -> String {
+    // Standard Algebraic Notation of a legal move
+    let pos = mv.from();
+    let (_, piece) = board.get(pos).unwrap().piece().unwrap();
+    let capture = is_capture(board, mv);
+    // the other pieces of the same kind that could go to the same square
+    let rivals: Vec<Pos> = board
+        .moves(color, true)
+        .iter()
+        .filter(|other| {
+            other.from() != pos
+                && other.to() == mv.to()
+                && other.promotion() == mv.promotion()
+                && same_kind(board.get(other.from()).unwrap().piece().unwrap().1, piece)
+        })
+        .map(|other| other.from())
+        .collect();
+    let file = pos2pgn(board, pos)[..1].to_string();
+    let rank = pos2pgn(board, pos)[1..].to_string();
+    let pawn = matches!(piece, Piece::Pawn { .. });
+    // pawn captures always give the file they come from
+    let disambiguation = if rivals.is_empty() {
+        if pawn && capture {
+            file
+        } else {
+            String::new()
+        }
+    } else if rivals.iter().all(|rival| rival.0 != pos.0) {
+        file
+    } else if rivals.iter().all(|rival| rival.1 != pos.1) && !(pawn && capture) {
+        rank
+    } else {
+        file + &rank
+    };
+    let mut res = format!(
+        "{}{}{}{}",
+        piece2pgn(piece),
+        disambiguation,
+        if capture { "x" } else { "" },
+        pos2pgn(board, mv.to())
+    );
+    if let Some(promoted) = mv.promotion() {
+        res += &format!("={}", piece2pgn(promoted));
+    }
+    let after = board.play(color, mv);
+    if let Some(Outcome::Checkmate(_)) = after.outcome(color.next()) {
+        res += "#";
+    } else if after.is_checked(color.next()) {
+        res += "+";
+    }
+    res
+}
+
+pub fn line2text(
+    board: &Board,
+    color: Color,
+    line: &[Move],
+    notation: impl Fn(&Board, Color, Move) -> String,
+) -> Vec<String> {
+    // a sequence of moves starting with color, such as the AI's principal variation, written with notation,
+    // it stops at the first illegal move since the search also looks at those
+    let mut board = board.clone();
+    let mut color = color;
+    let mut res = Vec::new();
+    for mv in line {
+        if !board.moves(color, true).contains(mv) {
+            break;
+        }
+        res.push(notation(&board, color, *mv));
+        board = board.play(color, *mv);
+        color = color.next();
+    }
+    res
+}
+
+pub fn line2san(board: &Board, color: Color, line: &[Move]) -> Vec<String> {
+    line2text(board, color, line, move2san)
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PgnError {
+    InvalidHeader(String),
+    InvalidFen(FenError),
+    InvalidMove { ply: usize, san: String },
+    IllegalMove { ply: usize, san: String },
+    AmbiguousMove { ply: usize, san: String },
+    UnclosedComment,
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::InvalidHeader(line) => write!(f, "invalid header: {}", line),
+            PgnError::InvalidFen(err) => write!(f, "invalid FEN header: {}", err),
+            PgnError::InvalidMove { ply, san } => {
+                write!(f, "'{}' at ply {} is not a move", san, ply)
+            }
+            PgnError::IllegalMove { ply, san } => {
+                write!(f, "'{}' at ply {} is not a legal move", san, ply)
+            }
+            PgnError::AmbiguousMove { ply, san } => {
+                write!(f, "'{}' at ply {} could be several moves", san, ply)
+            }
+            PgnError::UnclosedComment => write!(f, "a comment or variation is never closed"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<FenError> for PgnError {
+    fn from(err: FenError) -> Self {
+        PgnError::InvalidFen(err)
+    }
+}
+
+pub struct Pgn {
+    pub headers: Vec<(String, String)>,
+    pub game: Game,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl Pgn {
+    pub fn header(&self, tag: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name == tag)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub fn result2pgn(outcome: Option<Outcome>) -> &'static str {
+    match outcome {
+        Some(Outcome::Checkmate(Color::White)) => "1-0",
+        Some(Outcome::Checkmate(Color::Black)) => "0-1",
+        Some(_) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+pub fn game2pgn(game: &Game, headers: &[(String, String)]) -> String {
+    let result = result2pgn(game.outcome());
+    let initial = game.initial();
+    let mut tags: Vec<(String, String)> = [
+        ("Event", "?"),
+        ("Site", "?"),
+        ("Date", "????.??.??"),
+        ("Round", "?"),
+        ("White", "?"),
+        ("Black", "?"),
+        ("Result", result),
+    ]
+    .iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    if initial.board != standard_board() || initial.player != Color::White || initial.fullmove != 1
+    {
+        tags.push(("SetUp".to_string(), "1".to_string()));
+        tags.push(("FEN".to_string(), game2fen(&initial)));
+    }
+    for (name, value) in headers {
+        if let Some(tag) = tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            tag.1 = value.clone();
+        } else {
+            tags.push((name.clone(), value.clone()));
+        }
+    }
     let mut res = String::new();
-    for action in actions {
-        if let Action::Go(go_pos) = action {
-            res += format!("{}{}", pos2pgn(pos), pos2pgn(*go_pos)).as_str();
-        } else if let Action::Promotion(piece) = action {
-            res += format!("={}", piece2pgn(*piece)).as_str();
+    for (name, value) in tags.iter() {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        res += &format!("[{} \"{}\"]\n", name, value);
+    }
+    res += "\n";
+    // replay the game to write the moves, wrapping lines at 80 characters
+    let mut tokens = Vec::new();
+    let mut move_number = initial.fullmove;
+    let mut replay = initial;
+    for (i, mv) in game.history.iter().enumerate() {
+        if replay.player == Color::White {
+            tokens.push(format!("{}.", move_number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", move_number));
+        }
+        tokens.push(move2san(&replay.board, replay.player, *mv));
+        if replay.player == Color::Black {
+            move_number += 1;
         }
+        replay.make_move(*mv);
     }
+    tokens.push(
+        tags.iter()
+            .find(|(name, _)| name == "Result")
+            .unwrap()
+            .1
+            .clone(),
+    );
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() + 1 > 80 {
+            res += &line;
+            res += "\n";
+            line.clear();
+        }
+        if !line.is_empty() {
+            line += " ";
+        }
+        line += &token;
+    }
+    res += &line;
+    res += "\n";
     res
 }
+
+fn read_header(line: &str) -> Result<(String, String), PgnError> {
+    let invalid = || PgnError::InvalidHeader(line.to_string());
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+    let (name, value) = inner.split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.push(chars.next().ok_or_else(invalid)?);
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Ok((name.to_string(), unescaped))
+}
+
+fn read_tokens(movetext: &str) -> Result<Vec<String>, PgnError> {
+    // split the movetext in tokens, skipping comments, variations, move numbers and annotations
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    // en passant captures are sometimes suffixed with e.p. which is not part of SAN
+    let movetext = movetext.replace("e.p.", " ");
+    let mut chars = movetext.chars();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if depth == 0 => {
+                if !chars.any(|c| c == '}') {
+                    return Err(PgnError::UnclosedComment);
+                }
+            }
+            ';' if depth == 0 => while chars.next().is_some_and(|c| c != '\n') {},
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            c if c.is_whitespace() || c == '.' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if depth > 0 {
+        return Err(PgnError::UnclosedComment);
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens
+        .into_iter()
+        .filter(|token| !token.starts_with('$') && !token.chars().all(|c| c.is_ascii_digit()))
+        .collect())
+}
+
+pub fn san2move(board: &Board, color: Color, san: &str) -> Result<Move, PgnError> {
+    // find the legal move described by san, ply is left to the caller
+    let invalid = || PgnError::InvalidMove {
+        ply: 0,
+        san: san.to_string(),
+    };
+    let mut text = san.trim_end_matches(['+', '#', '!', '?']);
+    let mut promoted = None;
+    if let Some((rest, piece)) = text.split_once('=') {
+        let mut chars = piece.chars();
+        promoted = Some(chars.next().and_then(pgn2piece).ok_or_else(invalid)?);
+        if chars.next().is_some() {
+            return Err(invalid());
+        }
+        text = rest;
+    }
+    let mut chars = text.chars().peekable();
+    let kind = chars.next_if(|c| c.is_ascii_uppercase()).map(pgn2piece);
+    let kind = match kind {
+        Some(Some(piece)) => Some(piece),
+        Some(None) => return Err(invalid()),
+        None => None,
+    };
+    let rest: String = chars.collect();
+    // the destination is the last file letter and the rank digits that follow it
+    let split = rest
+        .rfind(|c: char| c.is_ascii_lowercase())
+        .ok_or_else(invalid)?;
+    let target = pgn2pos(board, &rest[split..]).ok_or_else(invalid)?;
+    let prefix = rest[..split].trim_end_matches('x');
+    let capture = rest[..split].ends_with('x');
+    let from_file = prefix
+        .chars()
+        .find(|c| c.is_ascii_lowercase())
+        .map(|c| c as i32 - 'a' as i32);
+    let from_rank = prefix
+        .trim_start_matches(|c: char| c.is_ascii_lowercase())
+        .parse::<i32>()
+        .ok()
+        .map(|rank| board.height as i32 - rank);
+    if prefix.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        return Err(invalid());
+    }
+    let candidates: Vec<Move> = board
+        .moves(color, true)
+        .iter()
+        .copied()
+        .filter(|mv| {
+            let (_, piece) = board.get(mv.from()).unwrap().piece().unwrap();
+            let kind_matches = match kind {
+                Some(kind) => same_kind(piece, kind),
+                None => matches!(piece, Piece::Pawn { .. }),
+            };
+            kind_matches
+                && mv.to() == target
+                && mv.promotion() == promoted
+                && (!capture || is_capture(board, *mv))
+                && from_file.is_none_or(|file| file == mv.from().0)
+                && from_rank.is_none_or(|rank| rank == mv.from().1)
+        })
+        .collect();
+    match candidates.len() {
+        0 => Err(PgnError::IllegalMove {
+            ply: 0,
+            san: san.to_string(),
+        }),
+        1 => Ok(candidates[0]),
+        _ => Err(PgnError::AmbiguousMove {
+            ply: 0,
+            san: san.to_string(),
+        }),
+    }
+}
+
+fn with_ply(err: PgnError, ply: usize) -> PgnError {
+    match err {
+        PgnError::InvalidMove { san, .. } => PgnError::InvalidMove { ply, san },
+        PgnError::IllegalMove { san, .. } => PgnError::IllegalMove { ply, san },
+        PgnError::AmbiguousMove { san, .. } => PgnError::AmbiguousMove { ply, san },
+        err => err,
+    }
+}
+
+pub fn pgn2game(pgn: &str) -> Result<Pgn, PgnError> {
+    let mut headers = Vec::new();
+    let mut movetext = String::new();
+    for line in pgn.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && movetext.trim().is_empty() {
+            headers.push(read_header(trimmed)?);
+        } else {
+            movetext += line;
+            movetext += "\n";
+        }
+    }
+    let mut game = match headers.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => fen2game(fen)?,
+        None => Game::new(standard_board(), Color::White),
+    };
+    let mut result = "*".to_string();
+    for token in read_tokens(&movetext)? {
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+            result = token;
+            break;
+        }
+        let ply = game.history.len() + 1;
+        let mv = san2move(&game.board, game.player, &token).map_err(|err| with_ply(err, ply))?;
+        game.make_move(mv);
+    }
+    Ok(Pgn {
+        headers,
+        game,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{tests::random_dims, Board, MAX_WIDTH},
+        fen::{fen2game, game2fen},
+        game::{standard_board, Game},
+        moves::Move,
+        pgn::{game2pgn, line2san, move2san, pgn2game, pgn2pos, pos2pgn, PgnError},
+        piece::{Color, Piece},
+        pos::Pos,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn standard_notation() {
+        let board = standard_board();
+        assert!(pos2pgn(&board, Pos(0, 7)) == "a1");
+        assert!(pos2pgn(&board, Pos(4, 4)) == "e4");
+        assert!(pos2pgn(&board, Pos(7, 0)) == "h8");
+        // the widest board ends on the z file
+        let board = Board::new(MAX_WIDTH, 1);
+        assert!(pos2pgn(&board, Pos(25, 0)) == "z1");
+    }
+
+    #[test]
+    fn notation_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let (width, height) = random_dims(&mut rng);
+            let board = Board::new(width, height);
+            for i in 0..width * height {
+                let pos = board.pos(i);
+                assert!(pgn2pos(&board, &pos2pgn(&board, pos)) == Some(pos));
+            }
+            assert!(pgn2pos(&board, &format!("a{}", height + 1)).is_none());
+            assert!(pgn2pos(&board, "a0").is_none());
+            assert!(pgn2pos(&board, "a-2147483648").is_none());
+            assert!(pgn2pos(&board, "a+1").is_none());
+            assert!(pgn2pos(&board, "a99999999999").is_none());
+        }
+    }
+
+    fn play_san(game: &mut Game, sans: &[&str]) -> Vec<String> {
+        // play moves given in SAN and return how move2san writes them back
+        sans.iter()
+            .map(|san| {
+                let mv = super::san2move(&game.board, game.player, san).unwrap();
+                let written = move2san(&game.board, game.player, mv);
+                game.make_move(mv);
+                written
+            })
+            .collect()
+    }
+
+    #[test]
+    fn san() {
+        let mut game = Game::new(standard_board(), Color::White);
+        let sans = [
+            "e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5", "d4", "c6", "Nf3", "Bg4", "Bf4", "e6", "h3",
+            "Bxf3", "Qxf3", "Bb4", "Be2", "Nd7", "a3", "Bxc3+",
+        ];
+        assert!(play_san(&mut game, &sans) == sans);
+        // disambiguation by file and by rank
+        let mut game = fen2game("4k3/8/8/8/8/8/R6R/4K3 w - - 0 1").unwrap();
+        assert!(play_san(&mut game, &["Rab2"]) == ["Rab2"]);
+        let mut game = fen2game("4k3/R7/8/8/8/8/R7/4K3 w - - 0 1").unwrap();
+        assert!(play_san(&mut game, &["R2a5"]) == ["R2a5"]);
+        // promotion with check
+        let mut game = fen2game("k7/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(play_san(&mut game, &["e8=Q+"]) == ["e8=Q+"]);
+        // en passant and mate
+        let mut game = fen2game("7k/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert!(play_san(&mut game, &["exd6"]) == ["exd6"]);
+        assert!(game.board.get(Pos(3, 3)).unwrap().piece().is_none());
+        let mut game = fen2game("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        assert!(play_san(&mut game, &["Qg7"]) == ["Qg7#"]);
+        // sideways pawns
+        let mut game = fen2game("4k3/8/8/8/8/8/P[e]7/4K3 w - - 0 1").unwrap();
+        assert!(play_san(&mut game, &["b2"]) == ["b2"]);
+    }
+
+    #[test]
+    fn line() {
+        let board = standard_board();
+        let line = [
+            Move::new(Pos(4, 6), Pos(4, 4)),
+            Move::new(Pos(4, 1), Pos(4, 3)),
+            Move::new(Pos(6, 7), Pos(5, 5)),
+            // e5 is taken, the line stops there
+            Move::new(Pos(4, 3), Pos(4, 4)),
+            Move::new(Pos(3, 1), Pos(3, 3)),
+        ];
+        assert!(line2san(&board, Color::White, &line) == ["e4", "e5", "Nf3"]);
+        assert!(line2san(&board, Color::Black, &line).is_empty());
+    }
+
+    #[test]
+    fn pgn_roundtrip() {
+        let mut game = Game::new(standard_board(), Color::White);
+        play_san(&mut game, &["f3", "e5", "g4", "Qh4#"]);
+        let pgn = game2pgn(&game, &[("White".to_string(), "Fool".to_string())]);
+        assert!(pgn.contains("[White \"Fool\"]\n"));
+        assert!(pgn.contains("[Result \"0-1\"]\n"));
+        assert!(pgn.ends_with("\n\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+        let read = pgn2game(&pgn).unwrap();
+        assert!(read.game.board == game.board);
+        assert!(read.header("White") == Some("Fool"));
+        assert!(read.result == "0-1");
+        // starting from another position
+        let mut game = fen2game("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        game.make_move(Move::new(Pos(4, 0), Pos(3, 0)));
+        let pgn = game2pgn(&game, &[]);
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]"));
+        assert!(pgn.contains("1... Kd8 *"));
+        assert!(pgn2game(&pgn).unwrap().game.board == game.board);
+        // and from a later move
+        let mut game = fen2game("4k3/8/8/8/8/8/4P3/4K3 b - - 0 12").unwrap();
+        game.make_move(Move::new(Pos(4, 0), Pos(3, 0)));
+        let pgn = game2pgn(&game, &[]);
+        assert!(pgn.contains("12... Kd8 *"));
+        assert!(game2fen(&pgn2game(&pgn).unwrap().game) == game2fen(&game));
+    }
+
+    #[test]
+    fn pgn_reader() {
+        let pgn = "[Event \"Test \\\"quoted\\\"\"]
+[Site \"?\"]
+
+1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 $1 ; a comment
+3. Bb5 a6 4. Ba4!? 1/2-1/2";
+        let read = pgn2game(pgn).unwrap();
+        assert!(read.header("Event") == Some("Test \"quoted\""));
+        assert!(read.game.history.len() == 7);
+        assert!(read.result == "1/2-1/2");
+        assert!(
+            read.game.board.get(Pos(0, 4)).unwrap().piece() == Some((Color::White, Piece::Bishop))
+        );
+        let en_passant = pgn2game("1. e4 a6 2. e5 d5 3. exd6 e.p. *").unwrap();
+        assert!(en_passant.game.captured.len() == 1);
+        assert!(matches!(
+            pgn2game("1. e4 e5 2. Ke3"),
+            Err(PgnError::IllegalMove { ply: 3, .. })
+        ));
+        assert!(matches!(
+            pgn2game("1. e4 e5 2. Zz9"),
+            Err(PgnError::InvalidMove { ply: 3, .. })
+        ));
+        assert!(matches!(
+            pgn2game("1. Qa-2147483648 *"),
+            Err(PgnError::InvalidMove { ply: 1, .. })
+        ));
+        assert!(matches!(
+            pgn2game("1. e4 {unclosed"),
+            Err(PgnError::UnclosedComment)
+        ));
+        assert!(matches!(
+            pgn2game("[Event]\n1. e4"),
+            Err(PgnError::InvalidHeader(_))
+        ));
+        let ambiguous = "[FEN \"4k3/8/8/8/8/8/R6R/4K3 w - - 0 1\"]\n1. Rb2";
+        assert!(matches!(
+            pgn2game(ambiguous),
+            Err(PgnError::AmbiguousMove { ply: 1, .. })
+        ));
+    }
+}
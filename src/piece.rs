@@ -1,8 +1,9 @@
-use crate::board::Board;
-use crate::pos::{Pos, DIAGS, LINES, LOS};
-use itertools::iproduct;
+use crate::board::{Board, Square, Terrain};
+use crate::moves::{Move, MoveList};
+use crate::pos::Pos;
+use std::ops::Range;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Color {
     White,
     Black,
@@ -17,20 +18,13 @@ impl Color {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum Action {
-    Go(Pos),
-    Take(Pos),
-    Promotion(Piece),
-}
-
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum PawnStatus {
     CanLeap,
     JustLeaped,
     CannotLeap,
 }
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Piece {
     Pawn {
         orientation: Pos,
@@ -49,159 +43,167 @@ fn pawn_moves(
     color: Color,
     orientation: Pos,
     status: PawnStatus,
-) -> Vec<Vec<Action>> {
-    let mut res = Vec::new();
+    res: &mut MoveList,
+) {
+    // a pawn promotes when it can't go any further, walls count as the end of the board
+    let mut push = |mv: Move| {
+        if board.is_edge(mv.to(), orientation) {
+            res.push(mv.promoting(Piece::Queen));
+            res.push(mv.promoting(Piece::Knight));
+        } else {
+            res.push(mv);
+        }
+    };
     // Non-Taking moves
     let forward_pos = orientation + pos;
     let leap_pos = orientation * 2 + pos;
+    let pawn = Piece::Pawn {
+        orientation,
+        status,
+    };
     // if there is a free cell forward
-    if let Some(None) = board.get(forward_pos) {
-        res.push(vec![Action::Go(forward_pos)]);
-        // if we can leap
-        if status == PawnStatus::CanLeap {
-            // and the square is available
-            if let Some(None) = board.get(leap_pos) {
-                res.push(vec![Action::Go(leap_pos)]);
+    if let Some(Square::Empty) = board.get(forward_pos) {
+        if pawn.can_enter(board.terrain(forward_pos)) {
+            push(Move::new(pos, forward_pos));
+            // if we can leap
+            if status == PawnStatus::CanLeap {
+                // and the square is available
+                if let Some(Square::Empty) = board.get(leap_pos) {
+                    if pawn.can_enter(board.terrain(leap_pos)) {
+                        push(Move::new(pos, leap_pos).leaping());
+                    }
+                }
             }
         }
     }
     // Taking moves
     for diag_dir in orientation.neighbors() {
         let diag_pos = diag_dir + pos;
+        if !pawn.can_enter(board.terrain(diag_pos)) {
+            continue;
+        }
         let diag = board.get(diag_pos);
-        // if it's a square
-        if let Some(square) = diag {
-            // if there's a piece on a taking square
-            if let Some((other_color, _)) = square {
-                // if it's an opponent
-                if color != *other_color {
-                    res.push(vec![Action::Go(diag_pos)]);
-                }
-            } else {
-                // the square is empty
-                let en_passant_pos = diag_pos + orientation * -1;
-                // if there's a piece in en passant pos
-                if let Some(Some((other_color, piece))) = board.get(en_passant_pos) {
-                    // if it's an opponent
-                    if color != *other_color {
-                        // if it's a pawn
-                        if let Piece::Pawn {
-                            orientation: _,
-                            status,
-                        } = piece
-                        {
-                            // if it just leaped forward
-                            if *status == PawnStatus::JustLeaped {
-                                res.push(vec![Action::Go(diag_pos), Action::Take(en_passant_pos)])
-                            }
+        // if there's a piece on a taking square
+        if let Some(Square::Piece(other_color, _)) = diag {
+            // if it's an opponent that is not protected by a fortification
+            if color != *other_color && board.terrain(diag_pos) != Terrain::Fortification {
+                push(Move::new(pos, diag_pos).capturing());
+            }
+        } else if let Some(Square::Empty) = diag {
+            // the square is empty
+            let en_passant_pos = diag_pos + orientation * -1;
+            // if there's a piece in en passant pos
+            if let Some(Square::Piece(other_color, piece)) = board.get(en_passant_pos) {
+                // if it's an opponent that is not protected by a fortification
+                if color != *other_color && board.terrain(en_passant_pos) != Terrain::Fortification
+                {
+                    // if it's a pawn
+                    if let Piece::Pawn {
+                        orientation: _,
+                        status,
+                    } = piece
+                    {
+                        // if it just leaped forward
+                        if *status == PawnStatus::JustLeaped {
+                            push(Move::en_passant(pos, diag_pos, en_passant_pos))
                         }
                     }
                 }
             }
         }
     }
-    // Handle promotions
-    let mut res_prom: Vec<Vec<Action>> = Vec::new();
-    for actions in res.iter() {
-        let mut last_pos = pos;
-        for action in actions {
-            if let Action::Go(go_pos) = action {
-                last_pos = *go_pos;
-            }
-        }
-        if board.get(last_pos + orientation).is_none() {
-            let mut action_q = actions.clone();
-            action_q.push(Action::Promotion(Piece::Queen));
-            let mut action_n = actions.clone();
-            action_n.push(Action::Promotion(Piece::Knight));
-            res_prom.push(action_q);
-            res_prom.push(action_n);
-        } else {
-            res_prom.push(actions.clone())
+}
+
+fn landing(board: &Board, pos: Pos, to: Pos, color: Color, piece: Piece) -> Option<Move> {
+    // the move of the piece to a square where it can end its move, either free or held by an opponent
+    let mv = match board.get(to) {
+        Some(Square::Empty) => Move::new(pos, to),
+        Some(Square::Piece(other_color, _)) if color != *other_color => {
+            Move::new(pos, to).capturing()
         }
-    }
-    res_prom
+        // walls, friends and out of the board
+        _ => return None,
+    };
+    piece.can_enter(board.terrain(to)).then_some(mv)
 }
 
-fn knight_moves(board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
-    iproduct!([-2, 2], [-1, 1])
-        .flat_map(|(long, short)| [Pos(long, short) + pos, Pos(short, long) + pos])
-        .filter(|take_pos| {
-            if let Some(square) = board.get(*take_pos) {
-                if let Some((other_color, _)) = square {
-                    if color == *other_color {
-                        return false;
-                    }
-                }
-                return true;
-            }
-            return false;
-        })
-        .map(|take_pos| vec![Action::Go(take_pos)])
-        .collect()
+fn knight_moves(board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
+    for i in board.attacks().knight(board.i(pos)) {
+        if let Some(mv) = landing(board, pos, board.pos(*i), color, Piece::Knight) {
+            res.push(mv);
+        }
+    }
 }
 
-fn los_moves(board: &Board, pos: Pos, color: Color, dirs: &[Pos]) -> Vec<Vec<Action>> {
-    let mut res = Vec::new();
+fn los_moves(
+    board: &Board,
+    pos: Pos,
+    color: Color,
+    piece: Piece,
+    dirs: Range<usize>,
+    res: &mut MoveList,
+) {
+    // dirs are indices in LOS, where the lines come before the diagonals
     for dir in dirs {
-        let mut curr_pos = pos;
-        loop {
-            curr_pos = curr_pos + *dir;
-            let line = board.get(curr_pos);
-            if let Some(square) = line {
-                if let Some((other_color, _)) = square {
+        for i in board.attacks().ray(board.i(pos), dir) {
+            let curr_pos = board.pos(*i);
+            if !piece.can_enter(board.terrain[*i]) {
+                // the piece can't go through this square
+                break;
+            }
+            match board.squares()[*i] {
+                Square::Piece(other_color, _) => {
                     // it's a square with a piece
-                    if color != *other_color {
+                    if color != other_color {
                         // it's a square with an opponent
-                        res.push(vec![Action::Go(curr_pos)]);
+                        res.push(Move::new(pos, curr_pos).capturing());
                     }
                     break;
-                } else {
-                    // it's a free square
-                    res.push(vec![Action::Go(curr_pos)]);
                 }
-            } else {
-                // it's out of the board
-                break;
+                Square::Empty => {
+                    // it's a free square, but pieces glide over ice
+                    if board.terrain[*i] != Terrain::Ice {
+                        res.push(Move::new(pos, curr_pos));
+                    }
+                }
+                // it's a wall
+                Square::Wall => break,
             }
         }
     }
-    res
 }
 
-fn bishop_moves(board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
-    los_moves(board, pos, color, &DIAGS)
+fn bishop_moves(board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
+    los_moves(board, pos, color, Piece::Bishop, 4..8, res)
 }
 
-fn rook_moves(board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
-    los_moves(board, pos, color, &LINES)
+fn rook_moves(board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
+    los_moves(board, pos, color, Piece::Rook, 0..4, res)
 }
 
-fn queen_moves(board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
-    los_moves(board, pos, color, &LOS)
+fn queen_moves(board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
+    los_moves(board, pos, color, Piece::Queen, 0..8, res)
 }
 
-fn king_moves(board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
+fn king_moves(board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
     // NOTE: we don't do castling because in the game you place your pieces at the start of the match
     // so it's both useless and inapplicable in our case (also a HUGE pain to implement)
-    LOS.iter()
-        .map(|los_dir| *los_dir + pos)
-        .filter(|take_pos| {
-            if let Some(square) = board.get(*take_pos) {
-                if let Some((other_color, _)) = square {
-                    if color == *other_color {
-                        return false;
-                    }
-                }
-                return true;
-            }
-            return false;
-        })
-        .map(|take_pos| vec![Action::Go(take_pos)])
-        .collect()
+    for i in board.attacks().king(board.i(pos)) {
+        if let Some(mv) = landing(board, pos, board.pos(*i), color, Piece::King) {
+            res.push(mv);
+        }
+    }
 }
 
 impl Piece {
+    pub fn can_enter(self, terrain: Terrain) -> bool {
+        match terrain {
+            // only knights can ford water, and the king is carried by his guards
+            Terrain::Water => matches!(self, Piece::Knight | Piece::King),
+            _ => true,
+        }
+    }
+
     pub fn begin_turn(self) -> Self {
         match self {
             Piece::Pawn {
@@ -243,17 +245,18 @@ impl Piece {
         }
     }
 
-    pub fn moves(self, board: &Board, pos: Pos, color: Color) -> Vec<Vec<Action>> {
+    pub fn moves(self, board: &Board, pos: Pos, color: Color, res: &mut MoveList) {
+        // add the moves of the piece standing on pos to res
         match self {
             Piece::Pawn {
                 orientation,
                 status,
-            } => pawn_moves(board, pos, color, orientation, status),
-            Piece::Knight => knight_moves(board, pos, color),
-            Piece::Bishop => bishop_moves(board, pos, color),
-            Piece::Rook => rook_moves(board, pos, color),
-            Piece::Queen => queen_moves(board, pos, color),
-            Piece::King => king_moves(board, pos, color),
+            } => pawn_moves(board, pos, color, orientation, status, res),
+            Piece::Knight => knight_moves(board, pos, color, res),
+            Piece::Bishop => bishop_moves(board, pos, color, res),
+            Piece::Rook => rook_moves(board, pos, color, res),
+            Piece::Queen => queen_moves(board, pos, color, res),
+            Piece::King => king_moves(board, pos, color, res),
         }
     }
 }
@@ -12,7 +12,7 @@ pub const LOS: [Pos; 8] = [
     Pos(-1, -1),
 ];
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Pos(pub i32, pub i32);
 
 impl Add for Pos {
@@ -0,0 +1,328 @@
+// Enemy AI profiles: a difficulty and a personality on top of ai::minmax, reproducible with a seeded rng
+use crate::ai::{minmax, TABLE_SIZE};
+use crate::board::{Board, Square};
+use crate::eval::{Evaluator, MaterialEvaluator};
+use crate::moves::Move;
+use crate::piece::{Color, Piece};
+use crate::pos::{Pos, LOS};
+use crate::transposition::TranspositionTable;
+use rand::Rng;
+use std::cell::Cell;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub fn depth(self) -> u32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 2,
+            Difficulty::Hard => 3,
+            Difficulty::Expert => 4,
+        }
+    }
+
+    // the probability of playing a random move other than the best one
+    pub fn blunder_chance(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.1,
+            Difficulty::Hard => 0.03,
+            Difficulty::Expert => 0.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Personality {
+    Balanced,
+    // favors captures and attacking the enemy king
+    Aggressive,
+    // favors keeping its pieces and keeping them together
+    Defensive,
+    // keeps its king away from the enemy
+    Cowardly,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnknownPreset(pub String);
+
+impl Display for UnknownPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown preset '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPreset {}
+
+impl FromStr for Difficulty {
+    type Err = UnknownPreset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            "expert" => Ok(Difficulty::Expert),
+            _ => Err(UnknownPreset(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Personality {
+    type Err = UnknownPreset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "balanced" => Ok(Personality::Balanced),
+            "aggressive" => Ok(Personality::Aggressive),
+            "defensive" => Ok(Personality::Defensive),
+            "cowardly" => Ok(Personality::Cowardly),
+            _ => Err(UnknownPreset(s.to_string())),
+        }
+    }
+}
+
+fn distance(pos1: Pos, pos2: Pos) -> i32 {
+    // the number of king moves between 2 squares
+    i32::max((pos1.0 - pos2.0).abs(), (pos1.1 - pos2.1).abs())
+}
+
+// the personality's taste added to a base evaluation, from the point of view of the AI's color
+struct PersonalityEvaluator<'a> {
+    base: &'a dyn Evaluator,
+    personality: Personality,
+    color: Color,
+}
+
+impl PersonalityEvaluator<'_> {
+    fn material(&self, board: &Board, color: Color) -> f32 {
+        board
+            .squares()
+            .iter()
+            .filter_map(|square| square.piece())
+            .filter(|(p_color, piece)| *p_color == color && *piece != Piece::King)
+            .map(|(_, piece)| self.base.piece_value(piece))
+            .sum()
+    }
+
+    fn pieces(board: &Board, color: Color) -> impl Iterator<Item = (Pos, Piece)> + '_ {
+        board
+            .squares()
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, square)| match square {
+                Square::Piece(p_color, piece) if *p_color == color => Some((board.pos(i), *piece)),
+                _ => None,
+            })
+    }
+
+    fn taste(&self, board: &Board) -> f32 {
+        let color = self.color;
+        match self.personality {
+            Personality::Balanced => 0.,
+            Personality::Aggressive => {
+                // enemy pieces are worth a quarter more, so trading is welcome
+                let trades = -0.25 * self.material(board, color.next());
+                let pressure = match board.king_pos(color.next()) {
+                    Some(king) => {
+                        Self::pieces(board, color)
+                            .filter(|(pos, piece)| {
+                                *piece != Piece::King && distance(*pos, king) <= 2
+                            })
+                            .count() as f32
+                            * 0.1
+                    }
+                    None => 0.,
+                };
+                trades + pressure
+            }
+            Personality::Defensive => {
+                // its own pieces are worth a quarter more, and they like company
+                let safety = 0.25 * self.material(board, color);
+                let company = Self::pieces(board, color)
+                    .filter(|(pos, _)| {
+                        LOS.iter().any(|dir| {
+                            matches!(board.get(*pos + *dir), Some(Square::Piece(p_color, _)) if *p_color == color)
+                        })
+                    })
+                    .count() as f32
+                    * 0.05;
+                safety + company
+            }
+            Personality::Cowardly => match board.king_pos(color) {
+                // the further the closest enemy, the better, up to a point
+                Some(king) => {
+                    let closest = Self::pieces(board, color.next())
+                        .map(|(pos, _)| distance(pos, king))
+                        .min()
+                        .unwrap_or(4);
+                    0.2 * closest.min(4) as f32
+                }
+                None => 0.,
+            },
+        }
+    }
+}
+
+impl Evaluator for PersonalityEvaluator<'_> {
+    fn piece_value(&self, piece: Piece) -> f32 {
+        self.base.piece_value(piece)
+    }
+
+    fn evaluate(&self, board: &Board, player: Color) -> f32 {
+        // the taste is only the AI's, the search still sees a zero sum game
+        let taste = self.taste(board);
+        self.base.evaluate(board, player) + if player == self.color { taste } else { -taste }
+    }
+}
+
+pub struct AiProfile {
+    pub difficulty: Difficulty,
+    pub personality: Personality,
+    // what the personality's taste is added to
+    pub evaluator: Box<dyn Evaluator>,
+    // what the searches learned, only valid for the side it was learned for since the taste depends on it
+    table: TranspositionTable,
+    table_color: Cell<Option<Color>>,
+}
+
+impl AiProfile {
+    pub fn new(difficulty: Difficulty, personality: Personality) -> Self {
+        Self {
+            difficulty,
+            personality,
+            evaluator: Box::new(MaterialEvaluator),
+            table: TranspositionTable::new(TABLE_SIZE),
+            table_color: Cell::new(None),
+        }
+    }
+
+    pub fn choose_move(&self, board: &Board, color: Color, rng: &mut impl Rng) -> Option<Move> {
+        let evaluator = PersonalityEvaluator {
+            base: &*self.evaluator,
+            personality: self.personality,
+            color,
+        };
+        if self.table_color.replace(Some(color)) != Some(color) {
+            self.table.clear();
+        }
+        let result = minmax(
+            board,
+            color,
+            self.difficulty.depth(),
+            &evaluator,
+            &self.table,
+        )?;
+        let blunder = rng.gen_bool(self.difficulty.blunder_chance());
+        if blunder && result.root_scores.len() > 1 {
+            let i = rng.gen_range(1..result.root_scores.len());
+            return Some(result.root_scores[i].0);
+        }
+        Some(result.best_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::tests::{random_board, random_dims},
+        eval::{Evaluator, MaterialEvaluator},
+        fen::fen2board,
+        game::{standard_board, Game},
+        moves::Move,
+        piece::Color,
+        pos::Pos,
+        profile::{
+            distance, AiProfile, Difficulty, Personality, PersonalityEvaluator, UnknownPreset,
+        },
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn self_play(seed: u64, plies: usize) -> Vec<Move> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let white = AiProfile::new(Difficulty::Easy, Personality::Aggressive);
+        let black = AiProfile::new(Difficulty::Easy, Personality::Defensive);
+        let mut game = Game::new(standard_board(), Color::White);
+        for _ in 0..plies {
+            if game.outcome().is_some() {
+                break;
+            }
+            let profile = match game.player {
+                Color::White => &white,
+                Color::Black => &black,
+            };
+            let mv = profile
+                .choose_move(&game.board, game.player, &mut rng)
+                .unwrap();
+            game.make_move(mv);
+        }
+        game.history
+    }
+
+    #[test]
+    fn reproducible() {
+        assert!(self_play(7, 20) == self_play(7, 20));
+        assert!(self_play(7, 20) != self_play(8, 20));
+    }
+
+    #[test]
+    fn presets() {
+        assert!("Hard".parse::<Difficulty>() == Ok(Difficulty::Hard));
+        assert!("cowardly".parse::<Personality>() == Ok(Personality::Cowardly));
+        assert!("reckless".parse::<Personality>() == Err(UnknownPreset("reckless".to_string())));
+        assert!(Difficulty::Easy.depth() < Difficulty::Expert.depth());
+        assert!(Difficulty::Expert.blunder_chance() == 0.);
+    }
+
+    #[test]
+    fn zero_sum() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for personality in [
+            Personality::Aggressive,
+            Personality::Defensive,
+            Personality::Cowardly,
+        ] {
+            let evaluator = PersonalityEvaluator {
+                base: &MaterialEvaluator,
+                personality,
+                color: Color::Black,
+            };
+            for _ in 0..50 {
+                let (width, height) = random_dims(&mut rng);
+                let board = random_board(&mut rng, width, height);
+                let score = evaluator.evaluate(&board, Color::White);
+                assert!((score + evaluator.evaluate(&board, Color::Black)).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn tastes() {
+        // trading the bishop for the knight is only welcome when aggressive
+        let (board, color) = fen2board("4k3/8/4p3/3n4/8/1B6/8/4K3 w - - 0 1").unwrap();
+        let trade = Move::new(Pos(1, 5), Pos(3, 3));
+        let mut rng = StdRng::seed_from_u64(0);
+        let aggressive = AiProfile::new(Difficulty::Expert, Personality::Aggressive);
+        let balanced = AiProfile::new(Difficulty::Expert, Personality::Balanced);
+        assert!(aggressive.choose_move(&board, color, &mut rng) == Some(trade));
+        assert!(balanced.choose_move(&board, color, &mut rng) != Some(trade));
+        // the king likes being away from the rooks when cowardly
+        let cowardly = PersonalityEvaluator {
+            base: &MaterialEvaluator,
+            personality: Personality::Cowardly,
+            color: Color::White,
+        };
+        let (far, _) = fen2board("7k/8/8/8/2rr4/8/8/4K3 w - - 0 1").unwrap();
+        let (near, _) = fen2board("7k/8/8/8/2rr4/4K3/8/8 w - - 0 1").unwrap();
+        assert!(distance(Pos(3, 4), Pos(4, 7)) == 3);
+        assert!(cowardly.taste(&far) > cowardly.taste(&near));
+    }
+}
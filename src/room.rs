@@ -0,0 +1,221 @@
+// Procedural rooms for the fights: the size, walls and terrain of the board and the zones both sides deploy in
+use crate::board::{Board, Square, Terrain};
+use crate::deploy::Zone;
+use crate::piece::Color;
+use crate::pos::{Pos, LOS};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Biome {
+    // pillars, fortifications and teleporters
+    Crypt,
+    // rocks and underground lakes
+    Cavern,
+    Volcano,
+    Glacier,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 4] = [Biome::Crypt, Biome::Cavern, Biome::Volcano, Biome::Glacier];
+
+    fn palette(self) -> (f64, Terrain) {
+        // the chance for an obstacle to be a wall, and the terrain of the others
+        match self {
+            Biome::Crypt => (0.7, Terrain::Fortification),
+            Biome::Cavern => (0.5, Terrain::Water),
+            Biome::Volcano => (0.3, Terrain::Lava),
+            Biome::Glacier => (0.2, Terrain::Ice),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Room {
+    pub board: Board,
+    pub white_zone: Zone,
+    pub black_zone: Zone,
+}
+
+// attempts at placing obstacles before giving up on them and leaving the room open
+const ATTEMPTS: usize = 20;
+
+fn is_open(board: &Board, pos: Pos) -> bool {
+    // a king can walk there, lava would kill it
+    board.get(pos) == Some(&Square::Empty) && board.terrain(pos) != Terrain::Lava
+}
+
+pub fn is_connected(board: &Board, zones: &[&Zone]) -> bool {
+    // true if a king can walk from any square of the zones to any other
+    let squares: Vec<Pos> = zones
+        .iter()
+        .flat_map(|zone| zone.squares.iter().copied())
+        .filter(|pos| is_open(board, *pos))
+        .collect();
+    let Some(start) = squares.first() else {
+        return false;
+    };
+    let mut seen = vec![false; board.width * board.height];
+    seen[board.i(*start)] = true;
+    let mut queue = VecDeque::from([*start]);
+    while let Some(pos) = queue.pop_front() {
+        for dir in LOS {
+            let next = pos + dir;
+            if is_open(board, next) && !seen[board.i(next)] {
+                seen[board.i(next)] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    squares.iter().all(|pos| seen[board.i(*pos)])
+}
+
+pub fn generate(seed: u64, depth: usize, biome: Biome, army: usize) -> Room {
+    // rooms get bigger and more cluttered deeper in the dungeon, and the zones can hold an army of that many pieces,
+    // obstacles are placed in pairs symmetric around the center so that no side is favored
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max_size = (8 + depth / 2).min(12);
+    let width = rng.gen_range(6..=max_size);
+    let height = rng.gen_range(6..=max_size);
+    let zone_depth = (if height >= 10 { 3 } else { 2 }).max(army.div_ceil(width));
+    // the room grows when the zones need more rows, there's always some room between them
+    let height = height.max(2 * zone_depth + 2);
+    let density = (0.15 + 0.02 * depth as f64).min(0.35);
+    // the zones are kept clear, only the rows between them get obstacles
+    let middle: Vec<Pos> = (zone_depth..height - zone_depth)
+        .flat_map(|y| (0..width).map(move |x| Pos(x as i32, y as i32)))
+        .collect();
+    let mirror = |pos: Pos| Pos(width as i32 - 1 - pos.0, height as i32 - 1 - pos.1);
+    for _ in 0..ATTEMPTS {
+        let mut board = Board::new(width, height);
+        let (wall_chance, terrain) = biome.palette();
+        for _ in 0..(middle.len() as f64 * density / 2.) as usize {
+            let pos = middle[rng.gen_range(0..middle.len())];
+            let wall = rng.gen_bool(wall_chance);
+            for pos in [pos, mirror(pos)] {
+                if wall {
+                    board.set(pos, Square::Wall);
+                } else {
+                    board.set(pos, Square::Empty);
+                    board.set_terrain(pos, terrain);
+                }
+            }
+        }
+        if biome == Biome::Crypt {
+            // a pair of teleporters linking both halves of the room
+            let pos = middle[rng.gen_range(0..middle.len())];
+            let (pos, link) = (pos, mirror(pos));
+            if pos != link
+                && board.get(pos) == Some(&Square::Empty)
+                && board.terrain(pos) == Terrain::Floor
+            {
+                board.link_teleporters(pos, link);
+            }
+        }
+        let white_zone = Zone::rows(&board, Color::White, zone_depth);
+        let black_zone = Zone::rows(&board, Color::Black, zone_depth);
+        if is_connected(&board, &[&white_zone, &black_zone]) {
+            return Room {
+                board,
+                white_zone,
+                black_zone,
+            };
+        }
+    }
+    // an open room is always connected
+    let board = Board::new(width, height);
+    Room {
+        white_zone: Zone::rows(&board, Color::White, zone_depth),
+        black_zone: Zone::rows(&board, Color::Black, zone_depth),
+        board,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, Square, Terrain},
+        deploy::{standard_army, Deployment, Zone},
+        piece::Color,
+        pos::Pos,
+        room::{generate, is_connected, Biome},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn connectivity() {
+        let board = Board::from_layout(
+            "
+            ......
+            ......
+            ##~###
+            !!.!!!
+            ......
+            ......
+            ",
+        );
+        let white = Zone::rows(&board, Color::White, 2);
+        let black = Zone::rows(&board, Color::Black, 2);
+        // the king can wade through water but not lava
+        assert!(is_connected(&board, &[&white, &black]));
+        let mut lava = board.clone();
+        lava.set_terrain(Pos(2, 3), Terrain::Lava);
+        assert!(!is_connected(&lava, &[&white, &black]));
+        let mut walled = board.clone();
+        walled.set(Pos(2, 2), Square::Wall);
+        assert!(!is_connected(&walled, &[&white, &black]));
+    }
+
+    #[test]
+    fn rooms() {
+        for biome in Biome::ALL {
+            let mut terrain = false;
+            for seed in 0..30 {
+                let depth = seed as usize % 10;
+                let room = generate(seed, depth, biome, 16);
+                assert!(room == generate(seed, depth, biome, 16));
+                let board = &room.board;
+                assert!((6..=12).contains(&board.width) && (6..=12).contains(&board.height));
+                assert!(is_connected(board, &[&room.white_zone, &room.black_zone]));
+                // the zones are clear, as big as their rows and hold the army
+                for zone in [&room.white_zone, &room.black_zone] {
+                    assert!(zone.squares.len() >= (2 * board.width).max(16));
+                    assert!(zone
+                        .squares
+                        .iter()
+                        .all(|pos| board.terrain(*pos) == Terrain::Floor));
+                }
+                terrain |= board
+                    .terrain
+                    .iter()
+                    .any(|terrain| *terrain != Terrain::Floor);
+                // boards of any size can be drawn
+                assert!(board.to_string().lines().count() == board.height);
+            }
+            assert!(terrain);
+        }
+        assert!(generate(0, 5, Biome::Crypt, 16) != generate(1, 5, Biome::Crypt, 16));
+    }
+
+    #[test]
+    fn armies_fit() {
+        // even the smallest rooms have zones deep enough for a full army
+        let mut rng = StdRng::seed_from_u64(0);
+        for seed in 0..200 {
+            let biome = Biome::ALL[seed as usize % Biome::ALL.len()];
+            let room = generate(seed, 0, biome, 16);
+            let mut deployment = Deployment::new(
+                room.board,
+                (room.white_zone, standard_army()),
+                (room.black_zone, standard_army()),
+            )
+            .unwrap();
+            deployment.auto_deploy(Color::White, &mut rng).unwrap();
+            deployment.auto_deploy(Color::Black, &mut rng).unwrap();
+        }
+        // a bigger army gets deeper zones
+        let room = generate(0, 0, Biome::Cavern, 40);
+        assert!(room.white_zone.squares.len() >= 40 && room.black_zone.squares.len() >= 40);
+    }
+}
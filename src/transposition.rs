@@ -0,0 +1,103 @@
+// Remembers the search results of positions by hash, so positions reached through different move orders
+// are only searched once
+use crate::moves::Move;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    // the score is the position's value at this depth
+    Exact,
+    // the search failed high, the value is at least the score
+    Lower,
+    // the search failed low, the value is at most the score
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u32,
+    pub score: f32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+// shared by the threads of a search, every slot has its own lock so they rarely wait on each other
+pub struct TranspositionTable {
+    entries: Vec<Mutex<Option<Entry>>>,
+}
+
+impl TranspositionTable {
+    pub fn new(capacity: usize) -> Self {
+        // the capacity is rounded up to a power of 2 so that hashes can be masked into indices
+        Self {
+            entries: (0..capacity.max(1).next_power_of_two())
+                .map(|_| Mutex::new(None))
+                .collect(),
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash & (self.entries.len() as u64 - 1)) as usize
+    }
+
+    pub fn get(&self, hash: u64) -> Option<Entry> {
+        self.entries[self.index(hash)]
+            .lock()
+            .unwrap()
+            .filter(|entry| entry.hash == hash)
+    }
+
+    pub fn insert(&self, entry: Entry) {
+        // a slot keeps the deepest search of its position, other positions simply replace it
+        let mut slot = self.entries[self.index(entry.hash)].lock().unwrap();
+        let keep = slot
+            .as_ref()
+            .is_some_and(|old| old.hash == entry.hash && old.depth > entry.depth);
+        if !keep {
+            *slot = Some(entry);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .iter()
+            .for_each(|entry| *entry.lock().unwrap() = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        moves::Move,
+        pos::Pos,
+        transposition::{Bound, Entry, TranspositionTable},
+    };
+
+    fn entry(hash: u64, depth: u32) -> Entry {
+        Entry {
+            hash,
+            depth,
+            score: depth as f32,
+            bound: Bound::Exact,
+            best_move: Some(Move::new(Pos(0, 0), Pos(0, 1))),
+        }
+    }
+
+    #[test]
+    fn replacement() {
+        let table = TranspositionTable::new(3);
+        assert!(table.entries.len() == 4);
+        table.insert(entry(1, 3));
+        assert!(table.get(1).unwrap().depth == 3);
+        // a shallower search of the same position doesn't replace a deeper one
+        table.insert(entry(1, 2));
+        assert!(table.get(1).unwrap().depth == 3);
+        // a colliding position does, and the old one is gone
+        table.insert(entry(5, 1));
+        assert!(table.get(1).is_none());
+        assert!(table.get(5).unwrap().depth == 1);
+        table.clear();
+        assert!(table.get(5).is_none());
+    }
+}
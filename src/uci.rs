@@ -0,0 +1,474 @@
+// Universal Chess Interface, to pit the AI against other engines or use it in chess GUIs
+use crate::ai::{score2mate, search, SearchLimits, TABLE_SIZE};
+use crate::board::Board;
+use crate::eval::{ConfigEvaluator, Evaluator, MaterialEvaluator};
+use crate::fen::fen2game;
+use crate::game::{standard_board, Game};
+use crate::moves::Move;
+use crate::pgn::{line2text, pgn2pos, pos2pgn};
+use crate::piece::{Color, Piece};
+use crate::pos::Pos;
+use crate::transposition::TranspositionTable;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const MAX_THREADS: usize = 64;
+// the depth searched when the one given can't be read, so that the search still ends
+const DEFAULT_DEPTH: u32 = 4;
+
+pub fn move2uci(board: &Board, mv: Move) -> String {
+    // long algebraic notation: start square, target square and lowercase promotion, like e7e8q
+    let promotion = match mv.promotion() {
+        Some(Piece::Queen) => "q",
+        Some(Piece::Knight) => "n",
+        Some(Piece::Rook) => "r",
+        Some(Piece::Bishop) => "b",
+        _ => "",
+    };
+    format!(
+        "{}{}{}",
+        pos2pgn(board, mv.from()),
+        pos2pgn(board, mv.to()),
+        promotion
+    )
+}
+
+pub fn uci2move(board: &Board, color: Color, uci: &str) -> Option<Move> {
+    // find the legal move written in long algebraic notation
+    board
+        .moves(color, true)
+        .iter()
+        .copied()
+        .find(|mv| move2uci(board, *mv) == uci)
+}
+
+fn line2uci(board: &Board, color: Color, line: &[Move]) -> String {
+    // a sequence of moves in long algebraic notation, up to the first illegal one since GUIs reject them
+    line2text(board, color, line, |board, _, mv| move2uci(board, mv)).join(" ")
+}
+
+fn split_squares(board: &Board, uci: &str) -> Option<(Pos, Pos)> {
+    // split the 2 squares of a move in long algebraic notation, ranks can have several digits,
+    // squares are ascii so anything else is rejected before slicing
+    if !uci.is_ascii() {
+        return None;
+    }
+    let second = uci[1..].find(|c: char| c.is_ascii_lowercase())? + 1;
+    let end = uci[second + 1..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(uci.len(), |i| i + second + 1);
+    Some((
+        pgn2pos(board, &uci[..second])?,
+        pgn2pos(board, &uci[second..end])?,
+    ))
+}
+
+#[derive(Default)]
+struct GoLimits {
+    depth: Option<u32>,
+    movetime: Option<Duration>,
+    infinite: bool,
+}
+
+fn read_go(tokens: &[&str], color: Color) -> GoLimits {
+    let mut limits = GoLimits::default();
+    let mut time_left = None;
+    let mut increment = 0;
+    let mut moves_to_go = 30;
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = tokens
+            .get(i + 1)
+            .and_then(|value| value.parse::<u64>().ok());
+        match (tokens[i], color) {
+            ("depth", _) => limits.depth = Some(value.map_or(DEFAULT_DEPTH, |depth| depth as u32)),
+            ("movetime", _) => limits.movetime = value.map(Duration::from_millis),
+            ("wtime", Color::White) | ("btime", Color::Black) => time_left = value,
+            ("winc", Color::White) | ("binc", Color::Black) => increment = value.unwrap_or(0),
+            ("movestogo", _) => moves_to_go = value.unwrap_or(30).max(1),
+            ("infinite", _) => {
+                limits.infinite = true;
+                i += 1;
+                continue;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    if limits.movetime.is_none() {
+        if let Some(time_left) = time_left {
+            // spread the remaining time over the next moves, keeping a margin to answer
+            let budget =
+                (time_left / moves_to_go + increment / 2).min(time_left.saturating_sub(50));
+            limits.movetime = Some(Duration::from_millis(budget.max(1)));
+        }
+    }
+    limits
+}
+
+pub struct Uci<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    game: Game,
+    stop: Arc<AtomicBool>,
+    searching: Option<JoinHandle<()>>,
+    infinite: bool,
+    threads: usize,
+    evaluator: Arc<dyn Evaluator>,
+    // what the searches learned, kept from one move to the next until the game or the evaluator changes
+    table: Arc<TranspositionTable>,
+}
+
+fn send<W: Write>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().unwrap();
+    // the GUI is gone if we can't write, there's nothing left to do about it
+    let _ = writeln!(output, "{}", line);
+    let _ = output.flush();
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output: Arc::new(Mutex::new(output)),
+            game: Game::new(standard_board(), Color::White),
+            stop: Arc::new(AtomicBool::new(false)),
+            searching: None,
+            infinite: false,
+            threads: 1,
+            evaluator: Arc::new(MaterialEvaluator),
+            table: Arc::new(TranspositionTable::new(TABLE_SIZE)),
+        }
+    }
+
+    pub fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            match line {
+                Ok(line) => {
+                    if !self.handle(&line) {
+                        self.stop_search();
+                        return;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        // when the input is closed, as in scripts, let the last search finish unless it would never end
+        if self.infinite {
+            self.stop_search();
+        } else if let Some(searching) = self.searching.take() {
+            searching.join().unwrap();
+        }
+    }
+
+    pub fn handle(&mut self, line: &str) -> bool {
+        // handle a command from the GUI, returns false when it's time to quit
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                send(&self.output, "id name DungeonChess");
+                send(&self.output, "id author Inspirateur");
+                send(
+                    &self.output,
+                    &format!(
+                        "option name Threads type spin default 1 min 1 max {}",
+                        MAX_THREADS
+                    ),
+                );
+                send(
+                    &self.output,
+                    "option name EvalFile type string default <empty>",
+                );
+                send(&self.output, "uciok");
+            }
+            Some(&"isready") => send(&self.output, "readyok"),
+            Some(&"ucinewgame") => {
+                self.stop_search();
+                self.game = Game::new(standard_board(), Color::White);
+                self.table.clear();
+            }
+            Some(&"position") => {
+                self.stop_search();
+                self.position(&tokens[1..]);
+            }
+            Some(&"go") => {
+                self.stop_search();
+                self.go(&tokens[1..]);
+            }
+            Some(&"stop") => self.stop_search(),
+            Some(&"setoption") => self.set_option(&tokens[1..]),
+            Some(&"quit") => return false,
+            // unknown commands are ignored as the protocol requires
+            _ => {}
+        }
+        true
+    }
+
+    fn position(&mut self, tokens: &[&str]) {
+        let moves_start = tokens
+            .iter()
+            .position(|token| *token == "moves")
+            .unwrap_or(tokens.len());
+        match tokens.first() {
+            Some(&"startpos") => self.game = Game::new(standard_board(), Color::White),
+            Some(&"fen") => match fen2game(&tokens[1..moves_start].join(" ")) {
+                Ok(game) => self.game = game,
+                Err(err) => {
+                    send(&self.output, &format!("info string invalid fen: {}", err));
+                    return;
+                }
+            },
+            _ => {
+                send(&self.output, "info string expected startpos or fen");
+                return;
+            }
+        }
+        for uci in tokens.iter().skip(moves_start + 1) {
+            match uci2move(&self.game.board, self.game.player, uci) {
+                Some(mv) => self.game.make_move(mv),
+                None => {
+                    let reason = if split_squares(&self.game.board, uci).is_some() {
+                        "illegal move"
+                    } else {
+                        "invalid move"
+                    };
+                    send(&self.output, &format!("info string {} {}", reason, uci));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn set_option(&mut self, tokens: &[&str]) {
+        // setoption name <name> value <value>, option names are case insensitive
+        match tokens {
+            ["name", name, "value", value] if name.eq_ignore_ascii_case("threads") => {
+                match value.parse::<usize>() {
+                    Ok(threads) if (1..=MAX_THREADS).contains(&threads) => self.threads = threads,
+                    _ => send(
+                        &self.output,
+                        &format!("info string invalid thread count {}", value),
+                    ),
+                }
+            }
+            // the path can contain spaces
+            ["name", name, "value", path @ ..] if name.eq_ignore_ascii_case("evalfile") => {
+                let path = path.join(" ");
+                if path.is_empty() || path == "<empty>" {
+                    self.evaluator = Arc::new(MaterialEvaluator);
+                } else {
+                    match ConfigEvaluator::load(&path) {
+                        Ok(evaluator) => self.evaluator = Arc::new(evaluator),
+                        Err(err) => {
+                            send(&self.output, &format!("info string {}", err));
+                            return;
+                        }
+                    }
+                }
+                // the scores learned with the previous evaluator are wrong now
+                self.table.clear();
+            }
+            _ => send(
+                &self.output,
+                &format!("info string unknown option {}", tokens.join(" ")),
+            ),
+        }
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        let limits = read_go(tokens, self.game.player);
+        self.infinite = limits.infinite;
+        let board = self.game.board.clone();
+        let color = self.game.player;
+        let output = self.output.clone();
+        let stop = self.stop.clone();
+        let threads = self.threads;
+        let evaluator = self.evaluator.clone();
+        let table = self.table.clone();
+        stop.store(false, Ordering::SeqCst);
+        self.searching = Some(thread::spawn(move || {
+            let search_limits = SearchLimits {
+                depth: limits.depth,
+                time: limits.movetime,
+                nodes: None,
+                threads,
+            };
+            let result = search(
+                &board,
+                color,
+                search_limits,
+                &evaluator,
+                &table,
+                &stop,
+                |iteration| {
+                    // a lost king is reported as a mate rather than an enormous material score
+                    let score = match score2mate(iteration.score) {
+                        Some(moves) => format!("mate {}", moves),
+                        None => format!("cp {}", (iteration.score * 100.) as i32),
+                    };
+                    send(
+                        &output,
+                        &format!(
+                            "info depth {} score {} nodes {} time {} pv {}",
+                            iteration.depth,
+                            score,
+                            iteration.nodes,
+                            iteration.elapsed.as_millis(),
+                            line2uci(&board, color, &iteration.pv)
+                        ),
+                    );
+                },
+            );
+            let best_move = result.map(|result| move2uci(&board, result.best_move));
+            // in infinite mode the answer must wait for the stop command
+            while limits.infinite && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            send(
+                &output,
+                &format!(
+                    "bestmove {}",
+                    best_move.unwrap_or_else(|| "0000".to_string())
+                ),
+            );
+        }));
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(searching) = self.searching.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            // the search thread doesn't panic unless there's a bug, in which case we want to know
+            searching.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fen::fen2board,
+        game::standard_board,
+        moves::Move,
+        pgn::move2san,
+        piece::{Color, Piece},
+        pos::Pos,
+        uci::{line2uci, move2uci, split_squares, uci2move, Uci, DEFAULT_DEPTH},
+    };
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_script(script: &str) -> Vec<String> {
+        let output = SharedBuffer::default();
+        let mut uci = Uci::new(output.clone());
+        uci.run(script.as_bytes());
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn notation() {
+        let board = standard_board();
+        assert!(move2uci(&board, Move::new(Pos(4, 6), Pos(4, 4))) == "e2e4");
+        assert!(uci2move(&board, Color::White, "g1f3") == Some(Move::new(Pos(6, 7), Pos(5, 5))));
+        assert!(uci2move(&board, Color::White, "e2e5").is_none());
+        let e4 = Move::new(Pos(4, 6), Pos(4, 4));
+        let line = [e4, Move::new(Pos(4, 1), Pos(4, 3)), e4];
+        assert!(line2uci(&board, Color::White, &line) == "e2e4 e7e5");
+        let (board, color) = fen2board("k7/4P3/8/8/8/8/8/4K3 w").unwrap();
+        assert!(
+            uci2move(&board, color, "e7e8n")
+                == Some(Move::new(Pos(4, 1), Pos(4, 0)).promoting(Piece::Knight))
+        );
+        let (board, _) = fen2board("12/12/12/12/12/12/12/12/12/12 w").unwrap();
+        assert!(split_squares(&board, "a10l1") == Some((Pos(0, 0), Pos(11, 9))));
+        assert!(split_squares(&board, "é2e4").is_none() && split_squares(&board, "e2é4").is_none());
+    }
+
+    #[test]
+    fn handshake() {
+        let lines = run_script("uci\nisready\nquit\n");
+        assert!(
+            lines
+                == [
+                    "id name DungeonChess",
+                    "id author Inspirateur",
+                    "option name Threads type spin default 1 min 1 max 64",
+                    "option name EvalFile type string default <empty>",
+                    "uciok",
+                    "readyok"
+                ]
+        );
+    }
+
+    #[test]
+    fn go_depth() {
+        let lines = run_script("position startpos moves e2e4 e7e5\ngo depth 2\n");
+        assert!(lines[0].starts_with("info depth 1 score cp "));
+        let bestmove = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        // the move must be legal in the position
+        let mut game = crate::game::Game::new(standard_board(), Color::White);
+        game.make_move(Move::new(Pos(4, 6), Pos(4, 4)));
+        game.make_move(Move::new(Pos(4, 1), Pos(4, 3)));
+        assert!(uci2move(&game.board, game.player, bestmove).is_some());
+        // a depth that can't be read still bounds the search
+        let lines = run_script("go depth x\n");
+        assert!(lines[lines.len() - 2].starts_with(&format!("info depth {} ", DEFAULT_DEPTH)));
+    }
+
+    #[test]
+    fn go_fen_mate() {
+        // black has a pawn to move so the AI can't mistake a stalemate for a win
+        let fen = "7k/p4Q2/6K1/8/8/8/8/8 w - - 0 1";
+        let lines = run_script(&format!("position fen {}\ngo depth 3\n", fen));
+        let bestmove = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        let (board, color) = fen2board(fen).unwrap();
+        let mv = uci2move(&board, color, bestmove).unwrap();
+        assert!(move2san(&board, color, mv).ends_with('#'));
+        assert!(lines[lines.len() - 2].starts_with("info depth 3 score mate 1 "));
+    }
+
+    #[test]
+    fn stop_and_errors() {
+        let lines = run_script(
+            "position startpos moves e2e5\nposition fen 8/8 x\nposition startpos\ngo infinite\nstop\nisready\n",
+        );
+        assert!(lines[0] == "info string illegal move e2e5");
+        assert!(lines[1].starts_with("info string invalid fen: "));
+        assert!(lines.iter().any(|line| line.starts_with("bestmove ")));
+        assert!(lines.last().unwrap() == "readyok");
+        let lines = run_script("position startpos moves é2e4\nisready\n");
+        assert!(lines == ["info string invalid move é2e4", "readyok"]);
+        let lines = run_script("setoption name Threads value 0\nsetoption name Hash value 16\n");
+        assert!(
+            lines
+                == [
+                    "info string invalid thread count 0",
+                    "info string unknown option name Hash value 16"
+                ]
+        );
+        let lines = run_script("setoption name EvalFile value no such file.toml\n");
+        assert!(lines[0].starts_with("info string could not read the evaluation file"));
+        let lines = run_script("setoption name Threads value 2\ngo depth 2\n");
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        // there is no move to play when mated
+        let lines = run_script("position fen 7k/6Q1/6K1/8/8/8/8/8 b\ngo depth 1\n");
+        assert!(lines == ["bestmove 0000"]);
+    }
+}
@@ -0,0 +1,242 @@
+// The units of an army that live on from fight to fight, the board only knows which piece is which unit
+use crate::board::{Board, Square};
+use crate::deploy::Recruit;
+use crate::piece::{Color, Piece};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct UnitId(pub u32);
+
+// the experience it takes to go from a level to the next is this times the level
+pub const XP_PER_LEVEL: u32 = 100;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Unit {
+    pub id: UnitId,
+    pub name: String,
+    // what the unit currently is, a pawn that was promoted stays a queen
+    pub piece: Piece,
+    pub xp: u32,
+    pub level: u32,
+    // the names of the relics the unit carries
+    pub relics: Vec<String>,
+    // None when the unit doesn't use hit points
+    pub hp: Option<u32>,
+}
+
+impl Unit {
+    pub fn gain_xp(&mut self, xp: u32) -> u32 {
+        // returns the number of levels gained
+        self.xp += xp;
+        let mut levels = 0;
+        while self.xp >= self.level * XP_PER_LEVEL {
+            self.xp -= self.level * XP_PER_LEVEL;
+            self.level += 1;
+            levels += 1;
+        }
+        levels
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Roster {
+    units: Vec<Unit>,
+    // the units that died, in the order they fell
+    pub fallen: Vec<Unit>,
+    next_id: u32,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn recruit(&mut self, name: &str, piece: Piece) -> UnitId {
+        let id = UnitId(self.next_id);
+        self.next_id += 1;
+        self.units.push(Unit {
+            id,
+            name: name.to_string(),
+            piece,
+            xp: 0,
+            level: 1,
+            relics: Vec::new(),
+            hp: None,
+        });
+        id
+    }
+
+    pub fn units(&self) -> &[Unit] {
+        &self.units
+    }
+
+    pub fn get(&self, id: UnitId) -> Option<&Unit> {
+        self.units.iter().find(|unit| unit.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: UnitId) -> Option<&mut Unit> {
+        self.units.iter_mut().find(|unit| unit.id == id)
+    }
+
+    pub fn army(&self) -> Vec<Recruit> {
+        // the pieces to deploy for a fight, each knows its unit so that deploying keeps track of them
+        self.units
+            .iter()
+            .map(|unit| Recruit {
+                piece: unit.piece,
+                unit: Some(unit.id),
+            })
+            .collect()
+    }
+
+    pub fn after_fight(&mut self, board: &Board, color: Color) -> Vec<UnitId> {
+        // the units that aren't on the board anymore fell, the others keep what they became,
+        // returns the units that fell
+        let mut survivors = Vec::new();
+        let mut fell = Vec::new();
+        for mut unit in self.units.drain(..) {
+            // the enemy's roster can use the same ids, only our pieces can be our units
+            let survivor = (0..board.squares().len())
+                .map(|i| board.pos(i))
+                .find_map(|pos| match board.get(pos) {
+                    Some(Square::Piece(p_color, piece))
+                        if *p_color == color && board.unit(pos) == Some(unit.id) =>
+                    {
+                        Some(*piece)
+                    }
+                    _ => None,
+                });
+            match survivor {
+                Some(piece) => {
+                    unit.piece = piece;
+                    survivors.push(unit);
+                }
+                None => {
+                    fell.push(unit.id);
+                    self.fallen.push(unit);
+                }
+            }
+        }
+        self.units = survivors;
+        fell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, Square, Terrain},
+        deploy::{standard_army, Deployment, Recruit, Zone},
+        fen::fen2board,
+        game::standard_board,
+        moves::Move,
+        piece::{Color, Piece},
+        pos::Pos,
+        unit::{Roster, UnitId},
+    };
+
+    #[test]
+    fn play_keeps_units() {
+        // the pawn promotes, the knight goes through a teleporter and the bishop into lava
+        let (mut board, color) = fen2board("k7/2P5/8/8/8/8/8/KNB5 w - - 0 1").unwrap();
+        board.link_teleporters(Pos(2, 5), Pos(6, 4));
+        board.set_terrain(Pos(4, 5), Terrain::Lava);
+        for (i, pos) in [Pos(2, 1), Pos(1, 7), Pos(2, 7)].iter().enumerate() {
+            board.set_unit(*pos, Some(UnitId(i as u32)));
+        }
+        let board = board.play(
+            color,
+            Move::new(Pos(2, 1), Pos(2, 0)).promoting(Piece::Queen),
+        );
+        assert!(board.unit(Pos(2, 0)) == Some(UnitId(0)) && board.unit(Pos(2, 1)).is_none());
+        let board = board.play(color, Move::new(Pos(1, 7), Pos(2, 5)));
+        assert!(board.unit(Pos(6, 4)) == Some(UnitId(1)) && board.unit(Pos(2, 5)).is_none());
+        let board = board.play(color, Move::new(Pos(2, 7), Pos(4, 5)));
+        assert!(board.get(Pos(4, 5)) == Some(&Square::Empty) && board.unit(Pos(4, 5)).is_none());
+        // units don't change the position
+        let mut plain = board.clone();
+        plain.set_unit(Pos(6, 4), None);
+        assert!(plain.hash(color) == board.hash(color));
+    }
+
+    #[test]
+    fn fight() {
+        let mut roster = Roster::new();
+        let standard = standard_board();
+        for (i, square) in standard.squares()[48..].iter().enumerate() {
+            let (_, piece) = square.piece().unwrap();
+            roster.recruit(&format!("unit {}", i), piece);
+        }
+        // the knights swap places, each is still the unit that was put there
+        let room = Board::new(8, 8);
+        let mut deployment = Deployment::new(
+            room.clone(),
+            (Zone::rows(&room, Color::White, 2), roster.army()),
+            (Zone::rows(&room, Color::Black, 2), standard_army()),
+        )
+        .unwrap();
+        let setup: Vec<(Pos, Recruit)> = roster
+            .army()
+            .into_iter()
+            .enumerate()
+            .map(|(i, recruit)| match standard.pos(48 + i) {
+                Pos(1, 7) => (Pos(6, 7), recruit),
+                Pos(6, 7) => (Pos(1, 7), recruit),
+                pos => (pos, recruit),
+            })
+            .collect();
+        deployment.place_all(Color::White, &setup).unwrap();
+        for (i, square) in standard.squares()[..16].iter().enumerate() {
+            let (_, piece) = square.piece().unwrap();
+            deployment
+                .place(Color::Black, piece, standard.pos(i))
+                .unwrap();
+        }
+        let mut game = deployment.start(Color::White).unwrap();
+        let board = &game.board;
+        assert!(board.squares() == standard.squares());
+        let units = (0..64)
+            .filter(|i| board.unit(board.pos(*i)).is_some())
+            .count();
+        assert!(units == 16);
+        let (b1_knight, g1_knight) = (roster.units()[9].id, roster.units()[14].id);
+        assert!(
+            board.unit(Pos(6, 7)) == Some(b1_knight) && board.unit(Pos(1, 7)) == Some(g1_knight)
+        );
+        // black takes the pawn on e4
+        let e4 = Move::new(Pos(4, 6), Pos(4, 4));
+        let e4_unit = game.board.unit(Pos(4, 6)).unwrap();
+        for mv in [
+            e4,
+            Move::new(Pos(3, 1), Pos(3, 3)),
+            Move::new(Pos(6, 7), Pos(5, 5)),
+            Move::new(Pos(3, 3), Pos(4, 4)),
+        ] {
+            game.make_move(mv);
+        }
+        let fell = roster.after_fight(&game.board, Color::White);
+        assert!(fell == vec![e4_unit]);
+        assert!(roster.units().len() == 15 && roster.fallen[0].id == e4_unit);
+        assert!(roster.get(e4_unit).is_none());
+        let knight = game.board.unit(Pos(5, 5)).unwrap();
+        assert!(knight == b1_knight && roster.get(knight).unwrap().piece == Piece::Knight);
+        // the survivors are deployed again in the next fight
+        assert!(roster.army().len() == 15);
+        let unit = roster.get_mut(knight).unwrap();
+        assert!(unit.gain_xp(250) == 1 && unit.level == 2 && unit.xp == 150);
+        assert!(unit.gain_xp(50) == 1 && unit.level == 3 && unit.xp == 0);
+    }
+
+    #[test]
+    fn fight_against_roster() {
+        // both sides have a unit 0, the black one comes first on the board but only the white one is ours
+        let mut roster = Roster::new();
+        let id = roster.recruit("white knight", Piece::Knight);
+        let (mut board, _) = fen2board("k7/8/8/3n4/8/8/4N3/K7 w - - 0 1").unwrap();
+        board.set_unit(Pos(3, 3), Some(id));
+        board.set_unit(Pos(4, 6), Some(id));
+        assert!(roster.after_fight(&board, Color::White).is_empty());
+        assert!(roster.get(id).is_some());
+        board.set(Pos(4, 6), Square::Empty);
+        assert!(roster.after_fight(&board, Color::White) == vec![id]);
+    }
+}